@@ -1,22 +1,878 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
-use prometheus::Registry;
+use actix_web::rt;
+use anyhow::anyhow;
+use prometheus::core::{Collector, Desc};
+use prometheus::{Registry, proto};
+use tracing::{error, info, warn};
 
+use crate::config::BackgroundCollection;
+use crate::metrics::{ExporterMetrics, InstanceHealth, PoolStats};
 use crate::{collectors, instance};
 
-#[derive(Clone, Default)]
+// Time reserved for encoding and sending the response when the scrape timeout comes from Prometheus.
+const SCRAPE_TIMEOUT_OFFSET: Duration = Duration::from_millis(500);
+
+// Upper bound of the health check of an instance.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct CollectorState {
+    // Completion time of the last successful update.
+    last_success: Option<Instant>,
+    // Whether the last update failed or was skipped because the instance is down.
+    failed: bool,
+    // Set once the instance is removed by a config reload, background updates stop.
+    stopped: bool,
+    // Whether the server does not support the collector, its updates are skipped then.
+    unsupported: bool,
+}
+
+/// A collector together with the instance it collects metrics from.
+#[derive(Clone)]
+pub struct InstanceCollector {
+    pub dbi: Arc<instance::PostgresDB>,
+    pub collector: Box<dyn collectors::PG>,
+    state: Arc<RwLock<CollectorState>>,
+    // The collector as registered in the registry, used to unregister it.
+    registered: FreshCollector,
+}
+
+impl InstanceCollector {
+    /// Runs a single collector update and records its outcome in the exporter metrics.
+    /// The update is cancelled when it takes longer than `timeout`.
+    async fn update(&self, metrics: &ExporterMetrics, timeout: Option<Duration>) {
+        if !self.supported() {
+            return;
+        }
+
+        let started = Instant::now();
+
+        let update_result = match timeout {
+            Some(timeout) => match rt::time::timeout(timeout, self.collector.update()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    metrics.observe_timeout(&self.dbi.name, self.collector.name());
+                    Err(anyhow!("update timed out after {timeout:?}"))
+                }
+            },
+            None => self.collector.update().await,
+        };
+
+        metrics.observe_update(
+            &self.dbi.name,
+            self.collector.name(),
+            started.elapsed(),
+            update_result.is_ok(),
+        );
+        self.set_result(update_result.is_ok());
+
+        if let Err(err) = update_result {
+            error!(
+                "Problem running update collector {} for instance {}: {err}",
+                self.collector.name(),
+                self.dbi.name
+            );
+        }
+    }
+
+    fn set_result(&self, ok: bool) {
+        if let Ok(mut state) = self.state.write() {
+            state.failed = !ok;
+            if ok {
+                state.last_success = Some(Instant::now());
+            }
+        }
+    }
+
+    fn failed(&self) -> bool {
+        self.state.read().map(|s| s.failed).unwrap_or(true)
+    }
+
+    /// Checks the collector against the last known config of the server. Servers which were
+    /// never reached are assumed to support it, the update reports the connection error then.
+    fn supported(&self) -> bool {
+        let supported = self
+            .dbi
+            .current_cfg()
+            .is_none_or(|cfg| self.collector.is_supported(&cfg));
+
+        if let Ok(mut state) = self.state.write() {
+            if state.unsupported == supported {
+                info!(
+                    "collector {} for instance {} is {}",
+                    self.collector.name(),
+                    self.dbi.name,
+                    if supported {
+                        "enabled"
+                    } else {
+                        "disabled: not supported by the server"
+                    }
+                );
+            }
+            state.unsupported = !supported;
+            // a skipped update is not a failure.
+            if !supported {
+                state.failed = false;
+            }
+        }
+
+        supported
+    }
+
+    fn stop(&self) {
+        if let Ok(mut state) = self.state.write() {
+            state.stopped = true;
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.state.read().map(|s| s.stopped).unwrap_or(true)
+    }
+}
+
+/// Hides the metrics of a collector whose last update failed, so that values of a previous
+/// update are not reported as current.
+#[derive(Clone)]
+struct FreshCollector {
+    collector: Arc<dyn Collector>,
+    state: Arc<RwLock<CollectorState>>,
+}
+
+impl Collector for FreshCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.collector.desc()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        if self
+            .state
+            .read()
+            .map(|s| s.failed || s.unsupported)
+            .unwrap_or(true)
+        {
+            return Vec::new();
+        }
+
+        self.collector.collect()
+    }
+}
+
+#[derive(Clone)]
 pub struct PGEApp {
     pub instances: Vec<Arc<instance::PostgresDB>>,
-    pub collectors: Vec<Box<dyn collectors::PG>>,
+    pub collectors: Vec<InstanceCollector>,
     pub registry: Registry,
+    pub metrics: ExporterMetrics,
+    /// Upper bound of a whole scrape.
+    pub scrape_timeout: Option<Duration>,
+    /// Upper bound of a single collector update.
+    pub collector_timeout: Option<Duration>,
+    /// Collectors are updated in the background and scrapes serve the last snapshot when set.
+    pub background_collection: Option<BackgroundCollection>,
+    health: HashMap<String, InstanceHealth>,
 }
 
 impl PGEApp {
-    pub fn new() -> Self {
-        PGEApp::default()
+    pub fn new() -> anyhow::Result<Self> {
+        let registry = Registry::new();
+        let metrics = ExporterMetrics::new()?;
+        metrics.register(&registry)?;
+
+        Ok(PGEApp {
+            instances: Vec::new(),
+            collectors: Vec::new(),
+            registry,
+            metrics,
+            scrape_timeout: None,
+            collector_timeout: None,
+            background_collection: None,
+            health: HashMap::new(),
+        })
+    }
+
+    /// Adds an instance and registers its `pg_up` and `pg_exporter_last_scrape_error` metrics.
+    pub fn add_instance(&mut self, dbi: Arc<instance::PostgresDB>) -> anyhow::Result<()> {
+        let health = InstanceHealth::new(&dbi.name, &dbi.labels)?;
+        health.register(&self.registry)?;
+
+        self.health.insert(dbi.name.clone(), health);
+        self.instances.push(dbi);
+
+        Ok(())
+    }
+
+    /// Adds an instance together with all collectors enabled for it. Nothing of the instance is
+    /// left registered when it fails.
+    pub fn register_instance(&mut self, dbi: Arc<instance::PostgresDB>) -> anyhow::Result<()> {
+        let name = dbi.name.clone();
+        let result = self.register_collectors(dbi);
+
+        if result.is_err() {
+            self.remove_instance(&name);
+        }
+
+        result
+    }
+
+    /// Removes the instance, unregisters its metrics and stops its background updates.
+    /// Returns the instance so that the caller can close its connections.
+    pub fn remove_instance(&mut self, name: &str) -> Option<Arc<instance::PostgresDB>> {
+        let removed: Vec<InstanceCollector> = self
+            .collectors
+            .extract_if(.., |ic| ic.dbi.name == name)
+            .collect();
+
+        for ic in &removed {
+            ic.stop();
+            if let Err(e) = self.registry.unregister(Box::new(ic.registered.clone())) {
+                warn!(
+                    "unable to unregister collector {} for instance {name}: {e}",
+                    ic.collector.name()
+                );
+            }
+            self.metrics.remove_collector(name, ic.collector.name());
+        }
+
+        if let Some(health) = self.health.remove(name)
+            && let Err(e) = health.unregister(&self.registry)
+        {
+            warn!("unable to unregister health metrics of instance {name}: {e}");
+        }
+
+        let pos = self.instances.iter().position(|dbi| dbi.name == name)?;
+        Some(self.instances.remove(pos))
+    }
+
+    fn register_collectors(&mut self, dbi: Arc<instance::PostgresDB>) -> anyhow::Result<()> {
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_locks::NAME,
+            collectors::pg_locks::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_postmaster::NAME,
+            collectors::pg_postmaster::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_database::NAME,
+            collectors::pg_database::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_activity::NAME,
+            collectors::pg_activity::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_bgwirter::NAME,
+            collectors::pg_bgwirter::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_wal::NAME,
+            collectors::pg_wal::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_stat_io::NAME,
+            collectors::pg_stat_io::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_archiver::NAME,
+            collectors::pg_archiver::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_conflict::NAME,
+            collectors::pg_conflict::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_indexes::NAME,
+            collectors::pg_indexes::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_statements::NAME,
+            collectors::pg_statements::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_tables::NAME,
+            collectors::pg_tables::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_storage::NAME,
+            collectors::pg_storage::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_replication::NAME,
+            collectors::pg_replication::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_replication_slots::NAME,
+            collectors::pg_replication_slots::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_progress::NAME,
+            collectors::pg_progress::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_wraparound::NAME,
+            collectors::pg_wraparound::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_settings::NAME,
+            collectors::pg_settings::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_bloat::NAME,
+            collectors::pg_bloat::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_sequences::NAME,
+            collectors::pg_sequences::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_functions::NAME,
+            collectors::pg_functions::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_wal_receiver::NAME,
+            collectors::pg_wal_receiver::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_subscription::NAME,
+            collectors::pg_subscription::new,
+        )?;
+        self.register_collector(
+            Arc::clone(&dbi),
+            collectors::pg_custom::NAME,
+            collectors::pg_custom::new,
+        )?;
+
+        self.add_instance(dbi)
+    }
+
+    fn register_collector<C>(
+        &mut self,
+        dbi: Arc<instance::PostgresDB>,
+        name: &str,
+        new_fn: fn(Arc<instance::PostgresDB>) -> Option<C>,
+    ) -> anyhow::Result<()>
+    where
+        C: collectors::PG + Collector + Clone + 'static,
+    {
+        if !dbi.collector_enabled(name) {
+            info!("collector {name} is disabled");
+            return Ok(());
+        }
+
+        if let Some(c) = new_fn(Arc::clone(&dbi)) {
+            self.add_collector(dbi, c)?;
+        }
+        Ok(())
+    }
+
+    /// Registers the collector in the registry and schedules its updates.
+    pub fn add_collector<C>(&mut self, dbi: Arc<instance::PostgresDB>, col: C) -> anyhow::Result<()>
+    where
+        C: collectors::PG + Collector + Clone + 'static,
+    {
+        let state = Arc::new(RwLock::new(CollectorState::default()));
+        let registered = FreshCollector {
+            collector: Arc::new(col.clone()),
+            state: Arc::clone(&state),
+        };
+
+        self.registry.register(Box::new(registered.clone()))?;
+
+        self.collectors.push(InstanceCollector {
+            dbi,
+            collector: Box::new(col),
+            state,
+            registered,
+        });
+
+        Ok(())
+    }
+
+    /// Spawns a task per collector which updates it every configured interval.
+    /// Does nothing unless background collection is configured.
+    pub fn start_background_collection(&self) {
+        self.start_background_updates(|_| true);
+    }
+
+    /// Like [`Self::start_background_collection`] but only for the collectors of the instance,
+    /// used for instances added by a config reload.
+    pub fn start_background_collection_for(&self, instance: &str) {
+        self.start_background_updates(|ic| ic.dbi.name == instance);
+    }
+
+    fn start_background_updates(&self, filter: impl Fn(&InstanceCollector) -> bool) {
+        let Some(bg) = &self.background_collection else {
+            return;
+        };
+
+        for ic in self.collectors.iter().filter(|ic| filter(ic)).cloned() {
+            let interval = bg.interval_for(ic.collector.name());
+            // an update should not outlive its interval, otherwise snapshots get older and older.
+            let timeout = self.collector_timeout.unwrap_or(interval).min(interval);
+            let metrics = self.metrics.clone();
+
+            info!(
+                "collector {} for instance {} updates every {interval:?}",
+                ic.collector.name(),
+                ic.dbi.name
+            );
+
+            rt::spawn(async move {
+                while !ic.stopped() {
+                    let started = Instant::now();
+                    ic.update(&metrics, Some(timeout)).await;
+                    rt::time::sleep(interval.saturating_sub(started.elapsed())).await;
+                }
+            });
+        }
+    }
+
+    /// Prepares metrics of a scrape: checks the instances and, unless collectors are updated in
+    /// the background, updates collectors of the reachable instances.
+    pub async fn scrape(&self, scrape_timeout: Option<Duration>) {
+        let started = Instant::now();
+        let down = self.check_instances(scrape_timeout).await;
+
+        for ic in self
+            .collectors
+            .iter()
+            .filter(|ic| down.contains(&ic.dbi.name))
+        {
+            ic.set_result(false);
+        }
+
+        if self.background_collection.is_some() {
+            self.observe_staleness();
+        } else {
+            let remaining = scrape_timeout.map(|t| t.saturating_sub(started.elapsed()));
+            let collectors: Vec<_> = self
+                .collectors
+                .iter()
+                .filter(|ic| !down.contains(&ic.dbi.name))
+                .cloned()
+                .collect();
+            self.update_collectors(&collectors, remaining).await;
+        }
+
+        for (name, health) in &self.health {
+            let collector_failed = self
+                .collectors
+                .iter()
+                .any(|ic| ic.dbi.name == *name && ic.failed());
+            health.set(!down.contains(name), collector_failed);
+        }
+
+        self.observe_pools();
+    }
+
+    /// Runs the health check of every instance concurrently, records the SSL state of the
+    /// reachable ones and returns names of the instances which are down.
+    async fn check_instances(&self, scrape_timeout: Option<Duration>) -> HashSet<String> {
+        let timeout = scrape_timeout.map_or(HEALTH_CHECK_TIMEOUT, |t| t.min(HEALTH_CHECK_TIMEOUT));
+
+        let tasks: Vec<_> = self
+            .instances
+            .iter()
+            .cloned()
+            .map(|dbi| {
+                rt::spawn(async move {
+                    let result = match rt::time::timeout(timeout, dbi.check_health()).await {
+                        Ok(result) => result,
+                        Err(_) => Err(anyhow!("health check timed out after {timeout:?}")),
+                    };
+                    (dbi, result)
+                })
+            })
+            .collect();
+
+        let mut down = HashSet::new();
+        for task in tasks {
+            match task.await {
+                Ok((dbi, Ok(ssl))) => {
+                    if let Some(health) = self.health.get(&dbi.name) {
+                        health.set_ssl(Some(&ssl));
+                    }
+                }
+                Ok((dbi, Err(e))) => {
+                    warn!("instance {} is down: {e}", dbi.name);
+                    if let Some(health) = self.health.get(&dbi.name) {
+                        health.set_ssl(None);
+                    }
+                    down.insert(dbi.name.clone());
+                }
+                Err(e) => error!("instance health check task failed: {e}"),
+            }
+        }
+
+        down
+    }
+
+    /// Exports the age of the snapshot of every collector which completed at least one update.
+    pub fn observe_staleness(&self) {
+        for ic in &self.collectors {
+            let last_success = ic.state.read().ok().and_then(|s| s.last_success);
+            if let Some(last_success) = last_success {
+                self.metrics.observe_staleness(
+                    &ic.dbi.name,
+                    ic.collector.name(),
+                    last_success.elapsed(),
+                );
+            }
+        }
+    }
+
+    /// Updates the given collectors concurrently. Collectors which are still running when
+    /// `scrape_timeout` expires are cancelled, the rest of the metrics stay available.
+    async fn update_collectors(
+        &self,
+        collectors: &[InstanceCollector],
+        scrape_timeout: Option<Duration>,
+    ) {
+        let started = Instant::now();
+
+        let tasks: Vec<_> = collectors
+            .iter()
+            .cloned()
+            .map(|ic| {
+                let metrics = self.metrics.clone();
+                let collector_timeout = self.collector_timeout;
+                let task = {
+                    let ic = ic.clone();
+                    rt::spawn(async move { ic.update(&metrics, collector_timeout).await })
+                };
+                (ic, task)
+            })
+            .collect();
+
+        for (ic, mut task) in tasks {
+            let joined = match scrape_timeout {
+                Some(timeout) => {
+                    let remaining = timeout.saturating_sub(started.elapsed());
+                    match rt::time::timeout(remaining, &mut task).await {
+                        Ok(joined) => joined,
+                        Err(_) => {
+                            task.abort();
+                            warn!(
+                                "collector {} for instance {} cancelled: scrape timeout {timeout:?} exceeded",
+                                ic.collector.name(),
+                                ic.dbi.name
+                            );
+                            self.metrics
+                                .observe_timeout(&ic.dbi.name, ic.collector.name());
+                            self.metrics.observe_update(
+                                &ic.dbi.name,
+                                ic.collector.name(),
+                                started.elapsed(),
+                                false,
+                            );
+                            ic.set_result(false);
+                            continue;
+                        }
+                    }
+                }
+                None => task.await,
+            };
+
+            if let Err(e) = joined {
+                error!(
+                    "collector {} for instance {} task failed: {e}",
+                    ic.collector.name(),
+                    ic.dbi.name
+                );
+                ic.set_result(false);
+            }
+        }
+    }
+
+    /// Exports the utilization of the connection pools of all instances.
+    pub fn observe_pools(&self) {
+        self.metrics.reset_pools();
+        for dbi in &self.instances {
+            for (database, pool) in dbi.pools() {
+                let (peak_in_use, peak_waiting) = pool.usage().take_peaks();
+                self.metrics.observe_pool(
+                    &dbi.name,
+                    &database,
+                    &PoolStats {
+                        size: pool.size(),
+                        idle: pool.num_idle(),
+                        max: pool.options().get_max_connections(),
+                        peak_in_use,
+                        peak_waiting,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Computes the deadline of a scrape from the `X-Prometheus-Scrape-Timeout-Seconds` header value
+/// and the configured scrape timeout, whichever is shorter.
+pub fn scrape_timeout(header: Option<&str>, configured: Option<Duration>) -> Option<Duration> {
+    let requested = header
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .map(|timeout| {
+            // keep the whole timeout when it is too short to reserve the offset.
+            timeout
+                .checked_sub(SCRAPE_TIMEOUT_OFFSET)
+                .filter(|t| !t.is_zero())
+                .unwrap_or(timeout)
+        });
+
+    match (requested, configured) {
+        (Some(r), Some(c)) => Some(r.min(c)),
+        (r, c) => r.or(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    use async_trait::async_trait;
+    use prometheus::IntGauge;
+
+    // Reports the number of its successful updates, an update takes `delay` and fails when `fail`
+    // is set.
+    #[derive(Clone)]
+    struct FakeCollector {
+        name: &'static str,
+        gauge: IntGauge,
+        updates: Arc<AtomicU64>,
+        attempts: Arc<AtomicU64>,
+        fail: Arc<AtomicBool>,
+        delay: Duration,
+    }
+
+    impl FakeCollector {
+        fn new(name: &'static str, delay: Duration) -> Self {
+            Self {
+                name,
+                gauge: IntGauge::new(format!("fake_{name}_updates"), "Successful updates.")
+                    .expect("gauge should be created"),
+                updates: Arc::new(AtomicU64::new(0)),
+                attempts: Arc::new(AtomicU64::new(0)),
+                fail: Arc::new(AtomicBool::new(false)),
+                delay,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl collectors::PG for FakeCollector {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn update(&self) -> Result<(), anyhow::Error> {
+            rt::time::sleep(self.delay).await;
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(anyhow!("update failed"));
+            }
+            let updates = self.updates.fetch_add(1, Ordering::SeqCst) + 1;
+            self.gauge.set(updates as i64);
+            Ok(())
+        }
+    }
+
+    impl Collector for FakeCollector {
+        fn desc(&self) -> Vec<&Desc> {
+            self.gauge.desc()
+        }
+
+        fn collect(&self) -> Vec<proto::MetricFamily> {
+            self.gauge.collect()
+        }
+    }
+
+    fn test_app() -> PGEApp {
+        let cfg = instance::Config {
+            dsn: "postgresql://postgres@127.0.0.1:1/postgres".to_string(),
+            ..Default::default()
+        };
+        let connect_options = cfg.connect_options().expect("options should be built");
+        let dbi = Arc::new(instance::PostgresDB::lazy("test", &cfg, connect_options));
+
+        let mut app = PGEApp::new().expect("app should be created");
+        app.add_instance(dbi).expect("instance should be added");
+        app
+    }
+
+    fn gathered(app: &PGEApp, name: &str) -> Option<f64> {
+        let mfs = app.registry.gather();
+        let mf = mfs.iter().find(|mf| mf.name() == name)?;
+        mf.get_metric().first().map(|m| m.get_gauge().value())
+    }
+
+    async fn wait_for(condition: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            rt::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("condition not met in time");
+    }
+
+    fn timeouts(app: &PGEApp, collector: &str) -> Option<f64> {
+        let mfs = app.registry.gather();
+        let mf = mfs
+            .iter()
+            .find(|mf| mf.name() == "pg_exporter_collector_timeouts_total")?;
+        mf.get_metric()
+            .iter()
+            .find(|m| m.get_label().iter().any(|l| l.value() == collector))
+            .map(|m| m.get_counter().value())
+    }
+
+    #[actix_web::test]
+    async fn timed_out_collector_is_cancelled_and_others_are_reported() {
+        let mut app = test_app();
+        app.collector_timeout = Some(Duration::from_millis(100));
+        let fast = FakeCollector::new("fast", Duration::ZERO);
+        let slow = FakeCollector::new("slow", Duration::from_secs(10));
+        app.add_collector(Arc::clone(&app.instances[0]), fast.clone())
+            .expect("collector should be added");
+        app.add_collector(Arc::clone(&app.instances[0]), slow.clone())
+            .expect("collector should be added");
+
+        let started = Instant::now();
+        app.update_collectors(&app.collectors, None).await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(gathered(&app, "fake_fast_updates"), Some(1.0));
+        assert_eq!(gathered(&app, "fake_slow_updates"), None);
+        assert_eq!(slow.attempts.load(Ordering::SeqCst), 0);
+        assert_eq!(timeouts(&app, "slow"), Some(1.0));
+        assert_eq!(timeouts(&app, "fast"), None);
+    }
+
+    #[actix_web::test]
+    async fn scrape_timeout_cancels_running_collectors() {
+        let mut app = test_app();
+        let fast = FakeCollector::new("fast", Duration::ZERO);
+        let slow = FakeCollector::new("slow", Duration::from_secs(10));
+        app.add_collector(Arc::clone(&app.instances[0]), fast.clone())
+            .expect("collector should be added");
+        app.add_collector(Arc::clone(&app.instances[0]), slow.clone())
+            .expect("collector should be added");
+
+        let started = Instant::now();
+        app.update_collectors(&app.collectors, Some(Duration::from_millis(200)))
+            .await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(gathered(&app, "fake_fast_updates"), Some(1.0));
+        assert_eq!(gathered(&app, "fake_slow_updates"), None);
+        assert_eq!(timeouts(&app, "slow"), Some(1.0));
+    }
+
+    #[actix_web::test]
+    async fn background_collection_serves_snapshot_and_hides_failed_updates() {
+        let mut app = test_app();
+        app.background_collection = Some(BackgroundCollection {
+            interval_seconds: Some(0.5),
+            collector_intervals: None,
+        });
+        let fake = FakeCollector::new("fake", Duration::ZERO);
+        app.add_collector(Arc::clone(&app.instances[0]), fake.clone())
+            .expect("collector should be added");
+
+        app.start_background_collection();
+        wait_for(|| fake.updates.load(Ordering::SeqCst) == 1).await;
+
+        // scrapes read the snapshot of the last update instead of updating the collector.
+        app.observe_staleness();
+        assert_eq!(gathered(&app, "fake_fake_updates"), Some(1.0));
+        assert_eq!(gathered(&app, "fake_fake_updates"), Some(1.0));
+        assert_eq!(fake.attempts.load(Ordering::SeqCst), 1);
+        assert!(gathered(&app, "pg_exporter_collector_staleness_seconds").is_some());
+
+        fake.fail.store(true, Ordering::SeqCst);
+        wait_for(|| fake.attempts.load(Ordering::SeqCst) == 2).await;
+        wait_for(|| gathered(&app, "fake_fake_updates").is_none()).await;
+        assert_eq!(
+            gathered(&app, "pg_exporter_collector_last_success"),
+            Some(0.0)
+        );
+
+        app.remove_instance("test");
+    }
+
+    #[test]
+    fn scrape_timeout_without_header_uses_config() {
+        assert_eq!(scrape_timeout(None, None), None);
+        assert_eq!(
+            scrape_timeout(None, Some(Duration::from_secs(5))),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn scrape_timeout_from_header_reserves_offset() {
+        assert_eq!(
+            scrape_timeout(Some("10"), None),
+            Some(Duration::from_millis(9500))
+        );
+        assert_eq!(
+            scrape_timeout(Some("0.25"), None),
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn scrape_timeout_picks_shorter_timeout() {
+        assert_eq!(
+            scrape_timeout(Some("10"), Some(Duration::from_secs(3))),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            scrape_timeout(Some("2"), Some(Duration::from_secs(30))),
+            Some(Duration::from_millis(1500))
+        );
     }
 
-    pub fn add_collector(&mut self, col: Box<dyn collectors::PG>) {
-        self.collectors.push(col);
+    #[test]
+    fn scrape_timeout_ignores_invalid_header() {
+        assert_eq!(scrape_timeout(Some("abc"), None), None);
+        assert_eq!(scrape_timeout(Some("-1"), None), None);
+        assert_eq!(
+            scrape_timeout(Some("1e20"), Some(Duration::from_secs(5))),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            scrape_timeout(Some("0"), Some(Duration::from_secs(1))),
+            Some(Duration::from_secs(1))
+        );
     }
 }
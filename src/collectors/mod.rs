@@ -1,31 +1,45 @@
 pub mod pg_activity;
 pub mod pg_archiver;
 pub mod pg_bgwirter;
+pub mod pg_bloat;
 pub mod pg_conflict;
+pub mod pg_custom;
 pub mod pg_database;
+pub mod pg_functions;
 pub mod pg_indexes;
 pub mod pg_locks;
 pub mod pg_postmaster;
+pub mod pg_progress;
 pub mod pg_replication;
 pub mod pg_replication_slots;
+pub mod pg_sequences;
+pub mod pg_settings;
 pub mod pg_stat_io;
 pub mod pg_statements;
 pub mod pg_storage;
+pub mod pg_subscription;
 pub mod pg_tables;
 pub mod pg_wal;
+pub mod pg_wal_receiver;
+pub mod pg_wraparound;
 
+use anyhow::bail;
 use async_trait::async_trait;
 use dyn_clone::DynClone;
 
+use crate::instance::PGConfig;
+
 const NAMESPACE: &str = "pg";
 
 // Postgres server versions
 const POSTGRES_V95: i64 = 90500;
-const POSTGRES_V96: i64 = 90600;
+pub const POSTGRES_V96: i64 = 90600;
 const POSTGRES_V10: i64 = 100000;
+const POSTGRES_V11: i64 = 110000;
 const POSTGRES_V12: i64 = 120000;
 const POSTGRES_V13: i64 = 130000;
 const POSTGRES_V14: i64 = 140000;
+const POSTGRES_V15: i64 = 150000;
 const POSTGRES_V16: i64 = 160000;
 const POSTGRES_V17: i64 = 170000;
 const POSTGRES_V18: i64 = 180000;
@@ -33,9 +47,85 @@ const POSTGRES_V18: i64 = 180000;
 // Minimal required version is 9.5
 pub const POSTGRES_VMIN_NUM: i64 = POSTGRES_V95;
 
+// Names of all available collectors.
+pub const COLLECTORS: [&str; 24] = [
+    pg_activity::NAME,
+    pg_archiver::NAME,
+    pg_bgwirter::NAME,
+    pg_bloat::NAME,
+    pg_conflict::NAME,
+    pg_custom::NAME,
+    pg_database::NAME,
+    pg_functions::NAME,
+    pg_indexes::NAME,
+    pg_locks::NAME,
+    pg_postmaster::NAME,
+    pg_progress::NAME,
+    pg_replication::NAME,
+    pg_replication_slots::NAME,
+    pg_sequences::NAME,
+    pg_settings::NAME,
+    pg_stat_io::NAME,
+    pg_statements::NAME,
+    pg_storage::NAME,
+    pg_subscription::NAME,
+    pg_tables::NAME,
+    pg_wal::NAME,
+    pg_wal_receiver::NAME,
+    pg_wraparound::NAME,
+];
+
+/// Selects the collectors registered for an instance. When `include` is set only the listed
+/// collectors are registered, collectors listed in `exclude` are never registered.
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct CollectorsConfig {
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+}
+
+impl CollectorsConfig {
+    pub fn is_enabled(&self, name: &str) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|include| include.iter().any(|c| c == name));
+        let excluded = self.exclude.iter().flatten().any(|c| c == name);
+
+        included && !excluded
+    }
+
+    /// Checks that only known collectors are listed.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for name in self
+            .include
+            .iter()
+            .flatten()
+            .chain(self.exclude.iter().flatten())
+        {
+            if !COLLECTORS.contains(&name.as_str()) {
+                bail!(
+                    "unknown collector '{}', available collectors: {}",
+                    name,
+                    COLLECTORS.join(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 pub trait PG: DynClone + Send + Sync {
+    /// Stable collector name, one of `COLLECTORS`.
+    fn name(&self) -> &'static str;
     async fn update(&self) -> Result<(), anyhow::Error>;
+
+    /// Reports whether the collector can run against the server with the given config.
+    /// It is re-evaluated before every update, as upgrades and failovers change the config.
+    fn is_supported(&self, _cfg: &PGConfig) -> bool {
+        true
+    }
 }
 
 impl Clone for Box<dyn PG> {
@@ -12,6 +12,8 @@ use crate::instance;
 
 use super::PG;
 
+pub const NAME: &str = "pg_activity";
+
 const ACTIVITY_QUERY: &str = "SELECT
     COALESCE(usename, backend_type) AS user, datname AS database, state, wait_event_type, wait_event,
     COALESCE(EXTRACT(EPOCH FROM clock_timestamp() - xact_start), 0)::FLOAT8 AS active_seconds,
@@ -416,7 +418,6 @@ pub struct PGActivityCollector {
     dbi: Arc<instance::PostgresDB>,
     data: Arc<RwLock<PGActivityStats>>,
     descs: Vec<Desc>,
-    up: Gauge,
     start_time: Gauge,
     wait_events: IntGaugeVec,
     states: IntGaugeVec,
@@ -429,14 +430,7 @@ pub struct PGActivityCollector {
 
 impl PGActivityCollector {
     pub fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<PGActivityCollector> {
-        let up = Gauge::with_opts(
-            Opts::new("up", "State of PostgreSQL service: 0 is down, 1 is up.")
-                .namespace(super::NAMESPACE)
-                .const_labels(dbi.labels.clone()),
-        )?;
-
         let mut descs = Vec::new();
-        descs.extend(up.desc().into_iter().cloned());
 
         let start_time = Gauge::with_opts(
             Opts::new("start_time_seconds", "Postgres start time, in unixtime.")
@@ -531,7 +525,6 @@ impl PGActivityCollector {
             dbi,
             data: Arc::new(RwLock::new(PGActivityStats::new())),
             descs,
-            up,
             start_time,
             wait_events,
             states,
@@ -558,18 +551,22 @@ pub struct PGActivity {
 
 #[async_trait]
 impl PG for PGActivityCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         //get pg_prepared_xacts stats
         let prepared = sqlx::query_scalar::<_, i64>(PREPARED_XACT_QUERY)
-            .fetch_one(&self.dbi.db)
+            .fetch_one(&mut *self.dbi.acquire().await?)
             .await?;
 
         let start_time: f64 = sqlx::query_scalar(START_TIME_QUERY)
-            .fetch_one(&self.dbi.db)
+            .fetch_one(&mut *self.dbi.acquire().await?)
             .await?;
 
         let pg_activity_rows: Vec<PGActivity> = sqlx::query_as(ACTIVITY_QUERY)
-            .fetch_all(&self.dbi.db)
+            .fetch_all(&mut *self.dbi.acquire().await?)
             .await?;
 
         let mut data_lock = match self.data.write() {
@@ -706,7 +703,7 @@ impl Collector for PGActivityCollector {
 
     fn collect(&self) -> Vec<proto::MetricFamily> {
         // collect MetricFamilies.
-        let mut mfs = Vec::with_capacity(9);
+        let mut mfs = Vec::with_capacity(8);
 
         let data_lock = match self.data.read() {
             Ok(lock) => lock,
@@ -717,6 +714,12 @@ impl Collector for PGActivityCollector {
             }
         };
 
+        self.wait_events.reset();
+        self.states.reset();
+        self.activity.reset();
+        self.inflight.reset();
+        self.vacuums.reset();
+
         let states: HashMap<&str, &HashMap<String, i64>> = HashMap::from([
             ("active", &data_lock.active),
             ("idle", &data_lock.idle),
@@ -812,13 +815,10 @@ impl Collector for PGActivityCollector {
             self.vacuums.with_label_values(&[k]).set(*v);
         }
 
-        // All activity metrics collected successfully, now we can collect up metric.
-        self.up.set(1.0);
         self.start_time.set(data_lock.start_time_seconds);
         self.prepared.set(data_lock.prepared);
         self.states_all.set(total);
 
-        mfs.extend(self.up.collect());
         mfs.extend(self.start_time.collect());
         mfs.extend(self.prepared.collect());
         mfs.extend(self.states.collect());
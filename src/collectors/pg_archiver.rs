@@ -8,9 +8,11 @@ use prometheus::proto::MetricFamily;
 use prometheus::{Gauge, IntCounter, IntGauge};
 use tracing::error;
 
-use crate::collectors::{PG, POSTGRES_V12, POSTGRES_V13};
+use crate::collectors::{PG, POSTGRES_V12};
 use crate::instance;
 
+pub const NAME: &str = "pg_archiver";
+
 const POSTGRES_WAL_ARCHIVING_QUERY: &str = "SELECT archived_count, failed_count,
 	EXTRACT(EPOCH FROM now() - last_archived_time)::FLOAT8 AS since_last_archive_seconds,
 	(SELECT count(*) FROM pg_ls_archive_statusdir() WHERE name ~'.ready') AS lag_files
@@ -48,23 +50,18 @@ pub struct PGArchiverCollector {
     lag_bytes: IntGauge,
 }
 
+// some system functions are not available, required Postgres 12 or newer
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version > POSTGRES_V12
+}
+
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGArchiverCollector> {
-    // some system functions are not available, required Postgres 12 or newer
-    if dbi
-        .current_cfg()
-        .map(|c| c.pg_version)
-        .unwrap_or(POSTGRES_V13)
-        > POSTGRES_V12
-    {
-        match PGArchiverCollector::new(dbi) {
-            Ok(result) => Some(result),
-            Err(e) => {
-                error!("error when create pg archiver collector: {}", e);
-                None
-            }
+    match PGArchiverCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg archiver collector: {}", e);
+            None
         }
-    } else {
-        None
     }
 }
 
@@ -146,6 +143,10 @@ impl Collector for PGArchiverCollector {
             }
         };
 
+        // counters are fed with the absolute values of the server, reset them before adding.
+        self.archived_total.reset();
+        self.failed_total.reset();
+
         for row in data_lock.iter() {
             self.archived_total.inc_by(row.archived as u64);
             self.failed_total.inc_by(row.failed as u64);
@@ -170,10 +171,18 @@ impl Collector for PGArchiverCollector {
 
 #[async_trait]
 impl PG for PGArchiverCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let mut pg_archiver_stats_rows =
             sqlx::query_as::<_, PGArchiverStats>(POSTGRES_WAL_ARCHIVING_QUERY)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?;
 
         let mut data_lock = match self.data.write() {
@@ -13,6 +13,8 @@ use crate::{collectors::POSTGRES_V17, instance};
 
 use super::PG;
 
+pub const NAME: &str = "pg_bgwriter";
+
 const BGWRITER_QUERY16: &str = "SELECT
 		checkpoints_timed, checkpoints_req, checkpoint_write_time, checkpoint_sync_time,
 		buffers_checkpoint, buffers_clean, maxwritten_clean,
@@ -297,6 +299,21 @@ impl Collector for PGBGwriterCollector {
             }
         };
 
+        // counters are fed with the absolute values of the server, reset them before adding.
+        self.alloc_bytes.reset();
+        self.bgwr_stats_age_seconds.reset();
+        self.buffers_backend_fsync.reset();
+        self.checkpoint_restartpointsdone.reset();
+        self.checkpoint_restartpointsreq.reset();
+        self.checkpoint_restartpointstimed.reset();
+        self.checkpoints.reset();
+        self.checkpoints_all.reset();
+        self.checkpoint_time.reset();
+        self.checkpoint_time_all.reset();
+        self.ckpt_stats_age_seconds.reset();
+        self.maxwritten_clean.reset();
+        self.written_bytes.reset();
+
         self.alloc_bytes.inc_by(data_lock.buffers_alloc as u64);
         self.bgwr_stats_age_seconds
             .inc_by(data_lock.bgwr_stats_age_seconds as u64);
@@ -366,15 +383,19 @@ impl Collector for PGBGwriterCollector {
 
 #[async_trait]
 impl PG for PGBGwriterCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let maybe_bgwr_stats = if cfg.pg_version < POSTGRES_V17 {
             sqlx::query_as::<_, PGBGwriterStats>(BGWRITER_QUERY16)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         } else {
             sqlx::query_as::<_, PGBGwriterStats>(BGWRITER_QUERY_LATEST)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         };
 
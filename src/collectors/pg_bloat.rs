@@ -0,0 +1,345 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{GaugeVec, IntGaugeVec, proto};
+use tracing::{error, warn};
+
+use crate::collectors::PG;
+use crate::instance;
+
+pub const NAME: &str = "pg_bloat";
+
+const BLOAT_SUBSYSTEM: &str = "bloat";
+
+// Estimates the size of user tables from the average width and null fraction of their columns
+// in pg_stats, the difference to the actual size is bloat. Tables with columns lacking statistics
+// or of type name can't be estimated and are skipped. The top-N tables by bloat are kept, all of
+// them when the top-N is not positive.
+const TABLE_BLOAT_ESTIMATE: &str = "WITH attrs AS (
+    SELECT tbl.oid AS tblid, ns.nspname AS schema, tbl.relname AS table,
+    greatest(tbl.reltuples, 0) AS reltuples, tbl.relpages AS heappages,
+    coalesce(toast.relpages, 0) AS toastpages, greatest(coalesce(toast.reltuples, 0), 0) AS toasttuples,
+    coalesce(substring(array_to_string(tbl.reloptions, ' ') FROM 'fillfactor=([0-9]+)')::smallint, 100) AS fillfactor,
+    current_setting('block_size')::numeric AS bs,
+    CASE WHEN version() ~ 'mingw32|64-bit|x86_64|ppc64|ia64|amd64|aarch64' THEN 8 ELSE 4 END AS ma,
+    24 AS page_hdr,
+    23 + CASE WHEN max(coalesce(s.null_frac, 0)) > 0 THEN (7 + count(s.attname)) / 8 ELSE 0 END AS tpl_hdr_size,
+    sum((1 - coalesce(s.null_frac, 0)) * coalesce(s.avg_width, 0)) AS tpl_data_size,
+    bool_or(att.atttypid = 'pg_catalog.name'::regtype)
+    OR sum(CASE WHEN att.attnum > 0 THEN 1 ELSE 0 END) <> count(s.attname) AS is_na
+    FROM pg_attribute att JOIN pg_class tbl ON att.attrelid = tbl.oid
+    JOIN pg_namespace ns ON ns.oid = tbl.relnamespace
+    LEFT JOIN pg_stats s ON s.schemaname = ns.nspname AND s.tablename = tbl.relname
+    AND NOT s.inherited AND s.attname = att.attname
+    LEFT JOIN pg_class toast ON tbl.reltoastrelid = toast.oid
+    WHERE NOT att.attisdropped AND tbl.relkind IN ('r', 'm')
+    AND ns.nspname NOT IN ('pg_catalog', 'information_schema') AND ns.nspname !~ '^pg_toast'
+    GROUP BY tbl.oid, ns.nspname, tbl.relname, tbl.reltuples, tbl.relpages, toast.relpages, toast.reltuples, tbl.reloptions),
+    tuples AS (SELECT *, 4 + tpl_hdr_size + tpl_data_size + (2 * ma)
+    - CASE WHEN tpl_hdr_size % ma = 0 THEN ma ELSE tpl_hdr_size % ma END
+    - CASE WHEN ceil(tpl_data_size)::int % ma = 0 THEN ma ELSE ceil(tpl_data_size)::int % ma END AS tpl_size
+    FROM attrs WHERE NOT is_na),
+    pages AS (SELECT tblid, schema, \"table\", fillfactor, bs, heappages + toastpages AS tblpages,
+    ceil(reltuples / nullif((bs - page_hdr) * fillfactor / (tpl_size * 100), 0)) + ceil(toasttuples / 4) AS est_tblpages
+    FROM tuples),
+    bloat AS (SELECT tblid, schema, \"table\", fillfactor, (bs * tblpages)::int8 AS size_bytes,
+    (bs * greatest(tblpages - coalesce(est_tblpages, tblpages), 0))::int8 AS bloat_bytes FROM pages),
+    top AS (SELECT * FROM (SELECT *, row_number() OVER (ORDER BY bloat_bytes DESC) AS rank FROM bloat) b
+    WHERE $1 <= 0 OR rank <= $1)";
+
+// Estimates the size of B-tree indexes from the statistics of the indexed columns, or of the
+// index itself for expressions. The top-N indexes by bloat are kept.
+const INDEX_BLOAT_QUERY: &str = "WITH idx AS (
+    SELECT ci.relname AS index, ci.reltuples, ci.relpages, i.indrelid AS tbloid, i.indexrelid AS idxoid,
+    coalesce(substring(array_to_string(ci.reloptions, ' ') FROM 'fillfactor=([0-9]+)')::smallint, 90) AS fillfactor,
+    string_to_array(textin(int2vectorout(i.indkey)), ' ')::int[] AS indkey,
+    generate_series(1, i.indnatts) AS attpos
+    FROM pg_index i JOIN pg_class ci ON ci.oid = i.indexrelid
+    WHERE ci.relam = (SELECT oid FROM pg_am WHERE amname = 'btree') AND ci.relpages > 0),
+    cols AS (
+    SELECT ct.relname AS table, ct.relnamespace, idx.index, idx.reltuples, idx.relpages, idx.idxoid, idx.fillfactor,
+    coalesce(a1.attname, a2.attname) AS attname, coalesce(a1.atttypid, a2.atttypid) AS atttypid,
+    CASE WHEN a1.attnum IS NULL THEN idx.index ELSE ct.relname END AS attrelname
+    FROM idx JOIN pg_class ct ON ct.oid = idx.tbloid
+    LEFT JOIN pg_attribute a1 ON idx.indkey[idx.attpos] <> 0 AND a1.attrelid = idx.tbloid AND a1.attnum = idx.indkey[idx.attpos]
+    LEFT JOIN pg_attribute a2 ON idx.indkey[idx.attpos] = 0 AND a2.attrelid = idx.idxoid AND a2.attnum = idx.attpos),
+    stats AS (
+    SELECT n.nspname AS schema, cols.table, cols.index, greatest(cols.reltuples, 0) AS reltuples, cols.relpages,
+    cols.fillfactor, current_setting('block_size')::numeric AS bs,
+    CASE WHEN version() ~ 'mingw32|64-bit|x86_64|ppc64|ia64|amd64|aarch64' THEN 8 ELSE 4 END AS maxalign,
+    24 AS pagehdr, 16 AS pageopqdata,
+    CASE WHEN max(coalesce(s.null_frac, 0)) = 0 THEN 8 ELSE 8 + ((32 + 8 - 1) / 8) END AS index_tuple_hdr_bm,
+    sum((1 - coalesce(s.null_frac, 0)) * coalesce(s.avg_width, 1024)) AS nulldatawidth,
+    bool_or(cols.atttypid = 'pg_catalog.name'::regtype) AS is_na
+    FROM cols JOIN pg_namespace n ON n.oid = cols.relnamespace
+    JOIN pg_stats s ON s.schemaname = n.nspname AND s.tablename = cols.attrelname AND s.attname = cols.attname
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname !~ '^pg_toast'
+    GROUP BY n.nspname, cols.table, cols.index, cols.reltuples, cols.relpages, cols.idxoid, cols.fillfactor),
+    widths AS (SELECT *, (index_tuple_hdr_bm + maxalign
+    - CASE WHEN index_tuple_hdr_bm % maxalign = 0 THEN maxalign ELSE index_tuple_hdr_bm % maxalign END
+    + nulldatawidth + maxalign
+    - CASE WHEN nulldatawidth = 0 THEN 0 WHEN nulldatawidth::int % maxalign = 0 THEN maxalign ELSE nulldatawidth::int % maxalign END
+    )::numeric AS nulldatahdrwidth
+    FROM stats WHERE NOT is_na),
+    pages AS (SELECT schema, \"table\", index, bs, relpages,
+    coalesce(1 + ceil(reltuples / nullif(floor((bs - pageopqdata - pagehdr) * fillfactor / (100 * (4 + nulldatahdrwidth)::float)), 0)), 0) AS est_pages
+    FROM widths),
+    bloat AS (SELECT schema, \"table\", index, (bs * relpages)::int8 AS size_bytes,
+    (bs * greatest(relpages - est_pages, 0))::int8 AS bloat_bytes FROM pages)
+    SELECT current_database() AS database, schema, \"table\", index, size_bytes, bloat_bytes
+    FROM (SELECT *, row_number() OVER (ORDER BY bloat_bytes DESC) AS rank FROM bloat) b
+    WHERE $1 <= 0 OR rank <= $1";
+
+const PGSTATTUPLE_SCHEMA_QUERY: &str =
+    "SELECT extnamespace::regnamespace::text FROM pg_extension WHERE extname = 'pgstattuple'";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGBloatStats {
+    database: String,
+    schema: String,
+    table: String,
+    index: Option<String>,
+    size_bytes: i64,
+    bloat_bytes: i64,
+}
+
+// Returns the table bloat query, the estimated top-N tables are measured with
+// pgstattuple_approx when the extension is installed in the given schema. Space reserved
+// by the fillfactor is not bloat.
+fn table_bloat_query(pgstattuple_schema: Option<&str>) -> String {
+    match pgstattuple_schema {
+        Some(schema) => format!(
+            "{TABLE_BLOAT_ESTIMATE} SELECT current_database() AS database, schema, \"table\", NULL::text AS index,
+            p.table_len AS size_bytes,
+            greatest(p.approx_free_space + p.dead_tuple_len - p.table_len * (100 - fillfactor) / 100, 0)::int8 AS bloat_bytes
+            FROM top, LATERAL {schema}.pgstattuple_approx(tblid) p"
+        ),
+        None => format!(
+            "{TABLE_BLOAT_ESTIMATE} SELECT current_database() AS database, schema, \"table\", NULL::text AS index,
+            size_bytes, bloat_bytes FROM top"
+        ),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PGBloatCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<Vec<PGBloatStats>>>,
+    descs: Vec<Desc>,
+    table_bytes: IntGaugeVec,
+    table_ratio: GaugeVec,
+    index_bytes: IntGaugeVec,
+    index_ratio: GaugeVec,
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGBloatCollector> {
+    match PGBloatCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg bloat collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGBloatCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+
+        let opts = |name: &str, help: &str| {
+            Opts::new(name, help)
+                .namespace(super::NAMESPACE)
+                .subsystem(BLOAT_SUBSYSTEM)
+                .const_labels(dbi.labels.clone())
+        };
+
+        let table_bytes = IntGaugeVec::new(
+            opts(
+                "table_bytes",
+                "Estimated wasted space of the table, in bytes. Measured with pgstattuple_approx when the extension is installed.",
+            ),
+            &["database", "schema", "table"],
+        )?;
+        descs.extend(table_bytes.desc().into_iter().cloned());
+
+        let table_ratio = GaugeVec::new(
+            opts(
+                "table_ratio",
+                "Estimated wasted space of the table as a fraction of its size.",
+            ),
+            &["database", "schema", "table"],
+        )?;
+        descs.extend(table_ratio.desc().into_iter().cloned());
+
+        let index_bytes = IntGaugeVec::new(
+            opts(
+                "index_bytes",
+                "Estimated wasted space of the B-tree index, in bytes.",
+            ),
+            &["database", "schema", "table", "index"],
+        )?;
+        descs.extend(index_bytes.desc().into_iter().cloned());
+
+        let index_ratio = GaugeVec::new(
+            opts(
+                "index_ratio",
+                "Estimated wasted space of the B-tree index as a fraction of its size.",
+            ),
+            &["database", "schema", "table", "index"],
+        )?;
+        descs.extend(index_ratio.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(Vec::new())),
+            descs,
+            table_bytes,
+            table_ratio,
+            index_bytes,
+            index_ratio,
+        })
+    }
+
+    // Queries the bloat of the tables and indexes of one database. pgstattuple_approx scans
+    // the tables it measures, so it is used only for a bounded top-N.
+    async fn database_bloat(
+        &self,
+        pool: &instance::TrackedPool,
+        top: i64,
+    ) -> anyhow::Result<Vec<PGBloatStats>> {
+        let pgstattuple_schema = if top > 0 {
+            sqlx::query_scalar::<_, String>(PGSTATTUPLE_SCHEMA_QUERY)
+                .fetch_optional(&mut *pool.acquire().await?)
+                .await?
+        } else {
+            None
+        };
+
+        let mut rows = match sqlx::query_as::<_, PGBloatStats>(&table_bloat_query(
+            pgstattuple_schema.as_deref(),
+        ))
+        .bind(top)
+        .fetch_all(&mut *pool.acquire().await?)
+        .await
+        {
+            Ok(rows) => rows,
+            // pgstattuple_approx needs the pg_stat_scan_tables role, fall back to the estimate.
+            Err(e) if pgstattuple_schema.is_some() => {
+                warn!("pg bloat collector: pgstattuple_approx failed, using estimates: {e}");
+                sqlx::query_as::<_, PGBloatStats>(&table_bloat_query(None))
+                    .bind(top)
+                    .fetch_all(&mut *pool.acquire().await?)
+                    .await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        rows.append(
+            &mut sqlx::query_as::<_, PGBloatStats>(INDEX_BLOAT_QUERY)
+                .bind(top)
+                .fetch_all(&mut *pool.acquire().await?)
+                .await?,
+        );
+
+        Ok(rows)
+    }
+}
+
+impl Collector for PGBloatCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(4);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg bloat collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        self.table_bytes.reset();
+        self.table_ratio.reset();
+        self.index_bytes.reset();
+        self.index_ratio.reset();
+
+        for row in data_lock.iter() {
+            let ratio = if row.size_bytes > 0 {
+                row.bloat_bytes as f64 / row.size_bytes as f64
+            } else {
+                0.0
+            };
+
+            match &row.index {
+                Some(index) => {
+                    let labels = [
+                        row.database.as_str(),
+                        row.schema.as_str(),
+                        row.table.as_str(),
+                        index.as_str(),
+                    ];
+                    self.index_bytes
+                        .with_label_values(&labels)
+                        .set(row.bloat_bytes);
+                    self.index_ratio.with_label_values(&labels).set(ratio);
+                }
+                None => {
+                    let labels = [
+                        row.database.as_str(),
+                        row.schema.as_str(),
+                        row.table.as_str(),
+                    ];
+                    self.table_bytes
+                        .with_label_values(&labels)
+                        .set(row.bloat_bytes);
+                    self.table_ratio.with_label_values(&labels).set(ratio);
+                }
+            }
+        }
+
+        mfs.extend(self.table_bytes.collect());
+        mfs.extend(self.table_ratio.collect());
+        mfs.extend(self.index_bytes.collect());
+        mfs.extend(self.index_ratio.collect());
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGBloatCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+        let mut bloat_rows = self
+            .dbi
+            .query_databases("pg bloat collector", |pool| async move {
+                self.database_bloat(&pool, cfg.pg_collect_top_bloat).await
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg bloat collector: can't acquire write lock. {}", e),
+        };
+
+        data_lock.clear();
+        data_lock.append(&mut bloat_rows);
+
+        Ok(())
+    }
+}
@@ -11,6 +11,8 @@ use tracing::error;
 use crate::collectors::{PG, POSTGRES_V16};
 use crate::instance;
 
+pub const NAME: &str = "pg_conflict";
+
 const POSTGRES_DATABASE_CONFLICT15: &str = "SELECT datname AS database,
     confl_tablespace, confl_lock, confl_snapshot, confl_bufferpin, confl_deadlock
     FROM pg_stat_database_conflicts WHERE pg_is_in_recovery() = 't'";
@@ -90,6 +92,8 @@ impl Collector for PGConflictCollector {
             }
         };
 
+        self.conflicts_total.reset();
+
         let database = data_lock.database.as_str();
         self.conflicts_total
             .with_label_values(&[database, "tablespace"])
@@ -117,15 +121,19 @@ impl Collector for PGConflictCollector {
 
 #[async_trait]
 impl PG for PGConflictCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let maybe_conflict_stats = if cfg.pg_version < POSTGRES_V16 {
             sqlx::query_as::<_, PGConflictStats>(POSTGRES_DATABASE_CONFLICT15)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         } else {
             sqlx::query_as::<_, PGConflictStats>(POSTGRES_DATABASE_CONFLICT_LATEST)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         };
 
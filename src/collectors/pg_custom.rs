@@ -0,0 +1,431 @@
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{CounterVec, GaugeVec, proto};
+use regex::Regex;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use sqlx::postgres::PgRow;
+use sqlx::{Column, Row, TypeInfo};
+use tracing::{error, info};
+
+use crate::collectors::PG;
+use crate::instance;
+
+pub const NAME: &str = "pg_custom";
+
+// Metric and label name formats accepted by Prometheus.
+const METRIC_NAME_RE: &str = "^[a-zA-Z_:][a-zA-Z0-9_:]*$";
+const LABEL_NAME_RE: &str = "^[a-zA-Z_][a-zA-Z0-9_]*$";
+
+/// A user-defined SQL query whose result columns are exported as metrics.
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct CustomQuery {
+    /// SQL text. Every row produces one series per value column.
+    pub query: String,
+    /// Metric name prefix, the resulting metric name is `<metric_prefix>_<column>`.
+    pub metric_prefix: String,
+    /// Columns used as metric labels.
+    pub labels: Option<Vec<String>>,
+    /// Columns exported as metric values.
+    pub values: Vec<CustomQueryValue>,
+    /// Minimal server version (server_version_num) the query is executed on, inclusive.
+    pub min_version: Option<i64>,
+    /// Maximal server version (server_version_num) the query is executed on, exclusive.
+    pub max_version: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct CustomQueryValue {
+    pub column: String,
+    #[serde(rename = "type")]
+    pub metric_type: Option<CustomMetricType>,
+    pub help: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, serde_derive::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomMetricType {
+    #[default]
+    Gauge,
+    Counter,
+}
+
+impl CustomQuery {
+    /// Checks that the query definition produces valid metrics.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let metric_re = Regex::new(METRIC_NAME_RE)?;
+        let label_re = Regex::new(LABEL_NAME_RE)?;
+
+        if self.query.trim().is_empty() {
+            bail!("custom query {}: query is empty", self.metric_prefix);
+        }
+
+        if !metric_re.is_match(&self.metric_prefix) {
+            bail!("custom query {}: invalid metric prefix", self.metric_prefix);
+        }
+
+        if self.values.is_empty() {
+            bail!(
+                "custom query {}: at least one value column is required",
+                self.metric_prefix
+            );
+        }
+
+        let mut columns = HashSet::new();
+        for label in self.labels.iter().flatten() {
+            if !label_re.is_match(label) || label.starts_with("__") {
+                bail!(
+                    "custom query {}: invalid label column name '{}'",
+                    self.metric_prefix,
+                    label
+                );
+            }
+            if !columns.insert(label.as_str()) {
+                bail!(
+                    "custom query {}: column '{}' is used more than once",
+                    self.metric_prefix,
+                    label
+                );
+            }
+        }
+
+        for value in &self.values {
+            if !label_re.is_match(&value.column) {
+                bail!(
+                    "custom query {}: invalid value column name '{}'",
+                    self.metric_prefix,
+                    value.column
+                );
+            }
+            if !columns.insert(value.column.as_str()) {
+                bail!(
+                    "custom query {}: column '{}' is used more than once",
+                    self.metric_prefix,
+                    value.column
+                );
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min_version, self.max_version)
+            && min >= max
+        {
+            bail!(
+                "custom query {}: min_version {} should be less than max_version {}",
+                self.metric_prefix,
+                min,
+                max
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Fully qualified metric names produced by the query.
+    pub fn metric_names(&self) -> Vec<String> {
+        self.values
+            .iter()
+            .map(|v| format!("{}_{}", self.metric_prefix, v.column))
+            .collect()
+    }
+
+    fn is_supported(&self, pg_version: i64) -> bool {
+        self.min_version.is_none_or(|min| pg_version >= min)
+            && self.max_version.is_none_or(|max| pg_version < max)
+    }
+}
+
+/// Validates a set of custom queries which are executed against the same instance.
+pub fn validate_queries(queries: &[CustomQuery]) -> anyhow::Result<()> {
+    let mut names = HashSet::new();
+    for q in queries {
+        q.validate()?;
+        for name in q.metric_names() {
+            if !names.insert(name.clone()) {
+                bail!(
+                    "custom query {}: duplicate metric name {}",
+                    q.metric_prefix,
+                    name
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+enum CustomMetric {
+    Gauge(GaugeVec),
+    Counter(CounterVec),
+}
+
+#[derive(Debug, Default)]
+struct PGCustomRow {
+    labels: Vec<String>,
+    values: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone)]
+struct CustomQueryMetrics {
+    def: CustomQuery,
+    metrics: Vec<CustomMetric>,
+}
+
+// PGCustomCollector exposes metrics produced by user-defined queries from the config.
+#[derive(Debug, Clone)]
+pub struct PGCustomCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<Vec<Vec<PGCustomRow>>>>,
+    descs: Vec<Desc>,
+    queries: Arc<Vec<CustomQueryMetrics>>,
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGCustomCollector> {
+    if dbi.custom_queries().is_empty() {
+        info!("no custom queries defined, skip pg custom collector");
+        return None;
+    }
+
+    match PGCustomCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg custom collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGCustomCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+        let mut queries = Vec::new();
+
+        for def in dbi.custom_queries() {
+            let label_names: Vec<&str> = def.labels.iter().flatten().map(|l| l.as_str()).collect();
+            let mut metrics = Vec::with_capacity(def.values.len());
+
+            for value in &def.values {
+                let help = value
+                    .help
+                    .clone()
+                    .unwrap_or_else(|| format!("Custom metric from column {}.", value.column));
+                let opts = Opts::new(format!("{}_{}", def.metric_prefix, value.column), help)
+                    .const_labels(dbi.labels.clone());
+
+                let metric = match value.metric_type.unwrap_or_default() {
+                    CustomMetricType::Gauge => {
+                        let m = GaugeVec::new(opts, &label_names)?;
+                        descs.extend(m.desc().into_iter().cloned());
+                        CustomMetric::Gauge(m)
+                    }
+                    CustomMetricType::Counter => {
+                        let m = CounterVec::new(opts, &label_names)?;
+                        descs.extend(m.desc().into_iter().cloned());
+                        CustomMetric::Counter(m)
+                    }
+                };
+                metrics.push(metric);
+            }
+
+            queries.push(CustomQueryMetrics {
+                def: def.clone(),
+                metrics,
+            });
+        }
+
+        let data = Arc::new(RwLock::new(
+            queries.iter().map(|_| Vec::new()).collect::<Vec<_>>(),
+        ));
+
+        Ok(Self {
+            dbi,
+            data,
+            descs,
+            queries: Arc::new(queries),
+        })
+    }
+
+    async fn fetch(&self, def: &CustomQuery) -> anyhow::Result<Vec<PGCustomRow>> {
+        let rows = sqlx::query(&def.query)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let labels = def
+                    .labels
+                    .iter()
+                    .flatten()
+                    .map(|col| label_value(row, col))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let values = def
+                    .values
+                    .iter()
+                    .map(|v| metric_value(row, &v.column))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                Ok(PGCustomRow { labels, values })
+            })
+            .collect()
+    }
+}
+
+impl Collector for PGCustomCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(self.descs.len());
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg custom collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        for (query, rows) in self.queries.iter().zip(data_lock.iter()) {
+            for (idx, metric) in query.metrics.iter().enumerate() {
+                // series are rebuilt from the latest rows only, so vanished label sets are dropped.
+                match metric {
+                    CustomMetric::Gauge(m) => m.reset(),
+                    CustomMetric::Counter(m) => m.reset(),
+                }
+
+                for row in rows {
+                    let Some(value) = row.values.get(idx).copied().flatten() else {
+                        continue;
+                    };
+                    let labels: Vec<&str> = row.labels.iter().map(|l| l.as_str()).collect();
+
+                    match metric {
+                        CustomMetric::Gauge(m) => m.with_label_values(&labels).set(value),
+                        CustomMetric::Counter(m) if value >= 0.0 => {
+                            m.with_label_values(&labels).inc_by(value)
+                        }
+                        CustomMetric::Counter(_) => error!(
+                            "pg custom collect: negative value {} for counter {}_{}; skip",
+                            value, query.def.metric_prefix, query.def.values[idx].column
+                        ),
+                    }
+                }
+
+                match metric {
+                    CustomMetric::Gauge(m) => mfs.extend(m.collect()),
+                    CustomMetric::Counter(m) => mfs.extend(m.collect()),
+                }
+            }
+        }
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGCustomCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+
+        let mut results = Vec::with_capacity(self.queries.len());
+        let mut failed = Vec::new();
+
+        for query in self.queries.iter() {
+            if !query.def.is_supported(cfg.pg_version) {
+                results.push(Vec::new());
+                continue;
+            }
+
+            match self.fetch(&query.def).await {
+                Ok(rows) => results.push(rows),
+                Err(e) => {
+                    error!(
+                        "pg custom collector: query {} failed: {}",
+                        query.def.metric_prefix, e
+                    );
+                    failed.push(query.def.metric_prefix.as_str());
+                    results.push(Vec::new());
+                }
+            }
+        }
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg custom collector: can't acquire write lock. {}", e),
+        };
+
+        *data_lock = results;
+
+        if !failed.is_empty() {
+            bail!(
+                "pg custom collector: {} of {} queries failed: {}",
+                failed.len(),
+                self.queries.len(),
+                failed.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn label_value(row: &PgRow, column: &str) -> anyhow::Result<String> {
+    let type_name = row.try_column(column)?.type_info().name().to_string();
+
+    let value = match type_name.as_str() {
+        "TEXT" | "VARCHAR" | "NAME" | "BPCHAR" | "CHAR" => {
+            row.try_get::<Option<String>, _>(column)?
+        }
+        "INT2" => row
+            .try_get::<Option<i16>, _>(column)?
+            .map(|v| v.to_string()),
+        "INT4" => row
+            .try_get::<Option<i32>, _>(column)?
+            .map(|v| v.to_string()),
+        "INT8" => row
+            .try_get::<Option<i64>, _>(column)?
+            .map(|v| v.to_string()),
+        "BOOL" => row
+            .try_get::<Option<bool>, _>(column)?
+            .map(|v| v.to_string()),
+        other => bail!("label column '{column}' has unsupported type {other}, cast it to text"),
+    };
+
+    Ok(value.unwrap_or_default())
+}
+
+fn metric_value(row: &PgRow, column: &str) -> anyhow::Result<Option<f64>> {
+    let type_name = row.try_column(column)?.type_info().name().to_string();
+
+    let value = match type_name.as_str() {
+        "FLOAT8" => row.try_get::<Option<f64>, _>(column)?,
+        "FLOAT4" => row.try_get::<Option<f32>, _>(column)?.map(f64::from),
+        "INT2" => row.try_get::<Option<i16>, _>(column)?.map(f64::from),
+        "INT4" => row.try_get::<Option<i32>, _>(column)?.map(f64::from),
+        "INT8" => row.try_get::<Option<i64>, _>(column)?.map(|v| v as f64),
+        "NUMERIC" => row
+            .try_get::<Option<Decimal>, _>(column)?
+            .map(|v| {
+                v.to_f64()
+                    .ok_or_else(|| anyhow!("value column '{column}' is out of range"))
+            })
+            .transpose()?,
+        "BOOL" => row
+            .try_get::<Option<bool>, _>(column)?
+            .map(|v| if v { 1.0 } else { 0.0 }),
+        other => bail!("value column '{column}' has unsupported type {other}, cast it to a number"),
+    };
+
+    Ok(value)
+}
@@ -2,18 +2,54 @@ use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
-use prometheus::IntGaugeVec;
 use prometheus::core::{Collector, Desc, Opts};
 use prometheus::proto;
+use prometheus::{CounterVec, GaugeVec, IntCounterVec, IntGaugeVec};
 use tracing::error;
 
+use crate::collectors::{POSTGRES_V12, POSTGRES_V14};
 use crate::instance;
 
 use super::PG;
 
+pub const NAME: &str = "pg_database";
+
 const PG_DATABASE_QUERY: &str = "SELECT datname AS name, pg_database_size(datname) AS size_bytes \
      FROM pg_database WHERE datname != ALL($1) AND datname != ''";
 
+// Checksum failures and session counters are not available until Postgres 12 and 14.
+const PG_STAT_DATABASE_QUERY95: &str = "SELECT datname AS name,
+    xact_commit, xact_rollback, blks_read, blks_hit,
+    tup_returned, tup_fetched, tup_inserted, tup_updated, tup_deleted,
+    conflicts, temp_files, temp_bytes, deadlocks, NULL::bigint AS checksum_failures,
+    blk_read_time, blk_write_time,
+    NULL::float8 AS session_time, NULL::float8 AS active_time, NULL::float8 AS idle_in_transaction_time,
+    NULL::bigint AS sessions, NULL::bigint AS sessions_abandoned, NULL::bigint AS sessions_fatal,
+    NULL::bigint AS sessions_killed,
+    EXTRACT(EPOCH FROM stats_reset)::float8 AS stats_reset
+    FROM pg_stat_database WHERE datname != ALL($1) AND datname != ''";
+
+const PG_STAT_DATABASE_QUERY12: &str = "SELECT datname AS name,
+    xact_commit, xact_rollback, blks_read, blks_hit,
+    tup_returned, tup_fetched, tup_inserted, tup_updated, tup_deleted,
+    conflicts, temp_files, temp_bytes, deadlocks, checksum_failures,
+    blk_read_time, blk_write_time,
+    NULL::float8 AS session_time, NULL::float8 AS active_time, NULL::float8 AS idle_in_transaction_time,
+    NULL::bigint AS sessions, NULL::bigint AS sessions_abandoned, NULL::bigint AS sessions_fatal,
+    NULL::bigint AS sessions_killed,
+    EXTRACT(EPOCH FROM stats_reset)::float8 AS stats_reset
+    FROM pg_stat_database WHERE datname != ALL($1) AND datname != ''";
+
+const PG_STAT_DATABASE_QUERY_LATEST: &str = "SELECT datname AS name,
+    xact_commit, xact_rollback, blks_read, blks_hit,
+    tup_returned, tup_fetched, tup_inserted, tup_updated, tup_deleted,
+    conflicts, temp_files, temp_bytes, deadlocks, checksum_failures,
+    blk_read_time, blk_write_time,
+    session_time, active_time, idle_in_transaction_time,
+    sessions, sessions_abandoned, sessions_fatal, sessions_killed,
+    EXTRACT(EPOCH FROM stats_reset)::float8 AS stats_reset
+    FROM pg_stat_database WHERE datname != ALL($1) AND datname != ''";
+
 const DATABASE_SUBSYSTEM: &str = "database";
 
 #[derive(sqlx::FromRow, Debug)]
@@ -22,16 +58,72 @@ struct PGDatabaseRow {
     size_bytes: i64,
 }
 
+#[derive(sqlx::FromRow, Debug, Default)]
+struct PGStatDatabaseRow {
+    name: String,
+    xact_commit: i64,
+    xact_rollback: i64,
+    blks_read: i64,
+    blks_hit: i64,
+    tup_returned: i64,
+    tup_fetched: i64,
+    tup_inserted: i64,
+    tup_updated: i64,
+    tup_deleted: i64,
+    conflicts: i64,
+    temp_files: i64,
+    temp_bytes: i64,
+    deadlocks: i64,
+    checksum_failures: Option<i64>,
+    // block and session times are reported in milliseconds.
+    blk_read_time: f64,
+    blk_write_time: f64,
+    session_time: Option<f64>,
+    active_time: Option<f64>,
+    idle_in_transaction_time: Option<f64>,
+    sessions: Option<i64>,
+    sessions_abandoned: Option<i64>,
+    sessions_fatal: Option<i64>,
+    sessions_killed: Option<i64>,
+    stats_reset: Option<f64>,
+}
+
 #[derive(Debug, Default)]
 pub struct PGDatabaseStats {
     size_bytes: HashMap<String, i64>,
+    stats: Vec<PGStatDatabaseRow>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PGDatabaseCollector {
     dbi: Arc<instance::PostgresDB>,
     data: Arc<RwLock<PGDatabaseStats>>,
+    descs: Vec<Desc>,
     size_bytes: IntGaugeVec,
+    xact_commits: IntCounterVec,
+    xact_rollbacks: IntCounterVec,
+    blocks_read: IntCounterVec,
+    blocks_hit: IntCounterVec,
+    tuples_returned: IntCounterVec,
+    tuples_fetched: IntCounterVec,
+    tuples_inserted: IntCounterVec,
+    tuples_updated: IntCounterVec,
+    tuples_deleted: IntCounterVec,
+    conflicts: IntCounterVec,
+    temp_files: IntCounterVec,
+    temp_bytes: IntCounterVec,
+    deadlocks: IntCounterVec,
+    checksum_failures: IntCounterVec,
+    blk_read_time: CounterVec,
+    blk_write_time: CounterVec,
+    session_time: CounterVec,
+    active_time: CounterVec,
+    idle_in_transaction_time: CounterVec,
+    sessions: IntCounterVec,
+    sessions_abandoned: IntCounterVec,
+    sessions_fatal: IntCounterVec,
+    sessions_killed: IntCounterVec,
+    stats_reset: GaugeVec,
 }
 
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGDatabaseCollector> {
@@ -46,29 +138,211 @@ pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGDatabaseCollector> {
 
 impl PGDatabaseCollector {
     pub fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<PGDatabaseCollector> {
-        let size_bytes = IntGaugeVec::new(
-            Opts::new("size_bytes", "Disk space used by the database")
+        let mut descs = Vec::new();
+
+        let opts = |name: &str, help: &str| {
+            Opts::new(name, help)
                 .namespace(super::NAMESPACE)
                 .subsystem(DATABASE_SUBSYSTEM)
-                .const_labels(dbi.labels.clone()),
+                .const_labels(dbi.labels.clone())
+        };
+        let int_counter =
+            |name: &str, help: &str| IntCounterVec::new(opts(name, help), &["datname"]);
+        let counter = |name: &str, help: &str| CounterVec::new(opts(name, help), &["datname"]);
+
+        let size_bytes = IntGaugeVec::new(
+            opts("size_bytes", "Disk space used by the database"),
+            &["datname"],
+        )?;
+        descs.extend(size_bytes.desc().into_iter().cloned());
+
+        let xact_commits = int_counter(
+            "xact_commits_total",
+            "Number of transactions in the database that have been committed",
+        )?;
+        descs.extend(xact_commits.desc().into_iter().cloned());
+
+        let xact_rollbacks = int_counter(
+            "xact_rollbacks_total",
+            "Number of transactions in the database that have been rolled back",
+        )?;
+        descs.extend(xact_rollbacks.desc().into_iter().cloned());
+
+        let blocks_read = int_counter(
+            "blocks_read_total",
+            "Number of disk blocks read in the database",
+        )?;
+        descs.extend(blocks_read.desc().into_iter().cloned());
+
+        let blocks_hit = int_counter(
+            "blocks_hit_total",
+            "Number of times disk blocks were found already in the buffer cache",
+        )?;
+        descs.extend(blocks_hit.desc().into_iter().cloned());
+
+        let tuples_returned = int_counter(
+            "tuples_returned_total",
+            "Number of live rows fetched by sequential scans and index entries returned by index scans",
+        )?;
+        descs.extend(tuples_returned.desc().into_iter().cloned());
+
+        let tuples_fetched = int_counter(
+            "tuples_fetched_total",
+            "Number of live rows fetched by index scans",
+        )?;
+        descs.extend(tuples_fetched.desc().into_iter().cloned());
+
+        let tuples_inserted = int_counter(
+            "tuples_inserted_total",
+            "Number of rows inserted by queries in the database",
+        )?;
+        descs.extend(tuples_inserted.desc().into_iter().cloned());
+
+        let tuples_updated = int_counter(
+            "tuples_updated_total",
+            "Number of rows updated by queries in the database",
+        )?;
+        descs.extend(tuples_updated.desc().into_iter().cloned());
+
+        let tuples_deleted = int_counter(
+            "tuples_deleted_total",
+            "Number of rows deleted by queries in the database",
+        )?;
+        descs.extend(tuples_deleted.desc().into_iter().cloned());
+
+        let conflicts = int_counter(
+            "conflicts_total",
+            "Number of queries canceled due to conflicts with recovery in the database",
+        )?;
+        descs.extend(conflicts.desc().into_iter().cloned());
+
+        let temp_files = int_counter(
+            "temp_files_total",
+            "Number of temporary files created by queries in the database",
+        )?;
+        descs.extend(temp_files.desc().into_iter().cloned());
+
+        let temp_bytes = int_counter(
+            "temp_bytes_total",
+            "Total amount of data written to temporary files by queries in the database",
+        )?;
+        descs.extend(temp_bytes.desc().into_iter().cloned());
+
+        let deadlocks = int_counter(
+            "deadlocks_total",
+            "Number of deadlocks detected in the database",
+        )?;
+        descs.extend(deadlocks.desc().into_iter().cloned());
+
+        let checksum_failures = int_counter(
+            "checksum_failures_total",
+            "Number of data page checksum failures detected in the database, since Postgres 12",
+        )?;
+        descs.extend(checksum_failures.desc().into_iter().cloned());
+
+        let blk_read_time = counter(
+            "blk_read_time_seconds_total",
+            "Time spent reading data file blocks by backends in the database, in seconds",
+        )?;
+        descs.extend(blk_read_time.desc().into_iter().cloned());
+
+        let blk_write_time = counter(
+            "blk_write_time_seconds_total",
+            "Time spent writing data file blocks by backends in the database, in seconds",
+        )?;
+        descs.extend(blk_write_time.desc().into_iter().cloned());
+
+        let session_time = counter(
+            "session_time_seconds_total",
+            "Time spent by database sessions, in seconds, since Postgres 14",
+        )?;
+        descs.extend(session_time.desc().into_iter().cloned());
+
+        let active_time = counter(
+            "active_time_seconds_total",
+            "Time spent executing SQL statements in the database, in seconds, since Postgres 14",
+        )?;
+        descs.extend(active_time.desc().into_iter().cloned());
+
+        let idle_in_transaction_time = counter(
+            "idle_in_transaction_time_seconds_total",
+            "Time spent idling while in a transaction in the database, in seconds, since Postgres 14",
+        )?;
+        descs.extend(idle_in_transaction_time.desc().into_iter().cloned());
+
+        let sessions = int_counter(
+            "sessions_total",
+            "Total number of sessions established to the database, since Postgres 14",
+        )?;
+        descs.extend(sessions.desc().into_iter().cloned());
+
+        let sessions_abandoned = int_counter(
+            "sessions_abandoned_total",
+            "Number of database sessions terminated because the connection to the client was lost, since Postgres 14",
+        )?;
+        descs.extend(sessions_abandoned.desc().into_iter().cloned());
+
+        let sessions_fatal = int_counter(
+            "sessions_fatal_total",
+            "Number of database sessions terminated by fatal errors, since Postgres 14",
+        )?;
+        descs.extend(sessions_fatal.desc().into_iter().cloned());
+
+        let sessions_killed = int_counter(
+            "sessions_killed_total",
+            "Number of database sessions terminated by operator intervention, since Postgres 14",
+        )?;
+        descs.extend(sessions_killed.desc().into_iter().cloned());
+
+        let stats_reset = GaugeVec::new(
+            opts(
+                "stats_reset_timestamp_seconds",
+                "Time at which the statistics of the database were last reset, as a unix timestamp",
+            ),
             &["datname"],
         )?;
+        descs.extend(stats_reset.desc().into_iter().cloned());
 
         Ok(PGDatabaseCollector {
             dbi,
             data: Arc::new(RwLock::new(PGDatabaseStats::default())),
+            descs,
             size_bytes,
+            xact_commits,
+            xact_rollbacks,
+            blocks_read,
+            blocks_hit,
+            tuples_returned,
+            tuples_fetched,
+            tuples_inserted,
+            tuples_updated,
+            tuples_deleted,
+            conflicts,
+            temp_files,
+            temp_bytes,
+            deadlocks,
+            checksum_failures,
+            blk_read_time,
+            blk_write_time,
+            session_time,
+            active_time,
+            idle_in_transaction_time,
+            sessions,
+            sessions_abandoned,
+            sessions_fatal,
+            sessions_killed,
+            stats_reset,
         })
     }
 }
 
 impl Collector for PGDatabaseCollector {
     fn desc(&self) -> Vec<&Desc> {
-        self.size_bytes.desc()
+        self.descs.iter().collect()
     }
 
     fn collect(&self) -> Vec<proto::MetricFamily> {
-        let mut mfs = Vec::with_capacity(1);
+        let mut mfs = Vec::with_capacity(25);
 
         let data_lock = match self.data.read() {
             Ok(lock) => lock,
@@ -78,31 +352,144 @@ impl Collector for PGDatabaseCollector {
             }
         };
 
+        let int_counters = [
+            &self.xact_commits,
+            &self.xact_rollbacks,
+            &self.blocks_read,
+            &self.blocks_hit,
+            &self.tuples_returned,
+            &self.tuples_fetched,
+            &self.tuples_inserted,
+            &self.tuples_updated,
+            &self.tuples_deleted,
+            &self.conflicts,
+            &self.temp_files,
+            &self.temp_bytes,
+            &self.deadlocks,
+            &self.checksum_failures,
+            &self.sessions,
+            &self.sessions_abandoned,
+            &self.sessions_fatal,
+            &self.sessions_killed,
+        ];
+        let counters = [
+            &self.blk_read_time,
+            &self.blk_write_time,
+            &self.session_time,
+            &self.active_time,
+            &self.idle_in_transaction_time,
+        ];
+
+        self.size_bytes.reset();
+        self.stats_reset.reset();
+        for counter in int_counters {
+            counter.reset();
+        }
+        for counter in counters {
+            counter.reset();
+        }
+
         data_lock
             .size_bytes
             .iter()
             .for_each(|(dbname, &size)| self.size_bytes.with_label_values(&[dbname]).set(size));
 
+        for row in &data_lock.stats {
+            let labels = [row.name.as_str()];
+
+            let values = [
+                Some(row.xact_commit),
+                Some(row.xact_rollback),
+                Some(row.blks_read),
+                Some(row.blks_hit),
+                Some(row.tup_returned),
+                Some(row.tup_fetched),
+                Some(row.tup_inserted),
+                Some(row.tup_updated),
+                Some(row.tup_deleted),
+                Some(row.conflicts),
+                Some(row.temp_files),
+                Some(row.temp_bytes),
+                Some(row.deadlocks),
+                row.checksum_failures,
+                row.sessions,
+                row.sessions_abandoned,
+                row.sessions_fatal,
+                row.sessions_killed,
+            ];
+            // counters not reported by the Postgres version are not exported.
+            for (counter, value) in int_counters.iter().zip(values) {
+                if let Some(value) = value {
+                    counter.with_label_values(&labels).inc_by(value as u64);
+                }
+            }
+
+            let times_ms = [
+                Some(row.blk_read_time),
+                Some(row.blk_write_time),
+                row.session_time,
+                row.active_time,
+                row.idle_in_transaction_time,
+            ];
+            for (counter, value) in counters.iter().zip(times_ms) {
+                if let Some(value) = value {
+                    counter.with_label_values(&labels).inc_by(value / 1000.0);
+                }
+            }
+
+            if let Some(stats_reset) = row.stats_reset {
+                self.stats_reset.with_label_values(&labels).set(stats_reset);
+            }
+        }
+
         mfs.extend(self.size_bytes.collect());
+        for counter in int_counters {
+            mfs.extend(counter.collect());
+        }
+        for counter in counters {
+            mfs.extend(counter.collect());
+        }
+        mfs.extend(self.stats_reset.collect());
+
         mfs
     }
 }
 
 #[async_trait]
 impl PG for PGDatabaseCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+
         let rows = sqlx::query_as::<_, PGDatabaseRow>(PG_DATABASE_QUERY)
             .bind(&self.dbi.excluded_db_names)
-            .fetch_all(&self.dbi.db)
+            .fetch_all(&mut *self.dbi.acquire().await?)
             .await?;
 
         let new_sizes: HashMap<String, i64> =
             rows.into_iter().map(|r| (r.name, r.size_bytes)).collect();
 
+        let stats_query = if cfg.pg_version < POSTGRES_V12 {
+            PG_STAT_DATABASE_QUERY95
+        } else if cfg.pg_version < POSTGRES_V14 {
+            PG_STAT_DATABASE_QUERY12
+        } else {
+            PG_STAT_DATABASE_QUERY_LATEST
+        };
+
+        let stats = sqlx::query_as::<_, PGStatDatabaseRow>(stats_query)
+            .bind(&self.dbi.excluded_db_names)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
         let mut data_lock = self.data.write().map_err(|e| {
             anyhow::anyhow!("pg database collector: can't acquire write lock. {}", e)
         })?;
         data_lock.size_bytes = new_sizes;
+        data_lock.stats = stats;
 
         Ok(())
     }
@@ -0,0 +1,226 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{CounterVec, IntCounterVec, proto};
+use tracing::{debug, error};
+
+use crate::collectors::PG;
+use crate::instance;
+
+pub const NAME: &str = "pg_functions";
+
+const FUNCTION_SUBSYSTEM: &str = "function";
+
+// The setting of the database the pool is connected to, it may be overridden per database.
+const TRACK_FUNCTIONS_QUERY: &str = "SELECT current_setting('track_functions')";
+
+// Overloaded functions are told apart by their arguments.
+const USER_FUNCTIONS_QUERY: &str = "SELECT current_database() AS database, schemaname AS schema,
+    funcname || '(' || pg_get_function_identity_arguments(funcid) || ')' AS function,
+    calls, total_time, self_time
+    FROM pg_stat_user_functions";
+
+const USER_FUNCTIONS_QUERY_TOPK: &str = "WITH stat AS (SELECT schemaname AS schema,
+    funcname || '(' || pg_get_function_identity_arguments(funcid) || ')' AS function,
+    calls, total_time, self_time,
+    (row_number() OVER (ORDER BY calls DESC NULLS LAST) <= $1) OR (row_number() OVER (ORDER BY total_time DESC NULLS LAST) <= $1) OR
+    (row_number() OVER (ORDER BY self_time DESC NULLS LAST) <= $1) AS visible
+    FROM pg_stat_user_functions)
+    SELECT current_database() AS database, schema, function, calls, total_time, self_time FROM stat WHERE visible
+    UNION ALL (SELECT current_database() AS database, 'all_shemas', 'all_other_functions',
+    SUM(COALESCE(calls, 0))::INT8, SUM(COALESCE(total_time, 0))::FLOAT8, SUM(COALESCE(self_time, 0))::FLOAT8
+    FROM stat WHERE NOT visible HAVING EXISTS (SELECT 1 FROM stat WHERE NOT visible))";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGFunctionStats {
+    database: String,
+    schema: String,
+    function: String,
+    calls: i64,
+    // total and self time are reported in milliseconds.
+    total_time: f64,
+    self_time: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PGFunctionsCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<Vec<PGFunctionStats>>>,
+    descs: Vec<Desc>,
+    calls: IntCounterVec,
+    total_time: CounterVec,
+    self_time: CounterVec,
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGFunctionsCollector> {
+    match PGFunctionsCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg functions collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGFunctionsCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+        let label_names = ["database", "schema", "function"];
+
+        let opts = |name: &str, help: &str| {
+            Opts::new(name, help)
+                .namespace(super::NAMESPACE)
+                .subsystem(FUNCTION_SUBSYSTEM)
+                .const_labels(dbi.labels.clone())
+        };
+
+        let calls = IntCounterVec::new(
+            opts(
+                "calls_total",
+                "Number of times the function has been called.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(calls.desc().into_iter().cloned());
+
+        let total_time = CounterVec::new(
+            opts(
+                "total_time_seconds_total",
+                "Time spent in the function and all other functions called by it, in seconds.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(total_time.desc().into_iter().cloned());
+
+        let self_time = CounterVec::new(
+            opts(
+                "self_time_seconds_total",
+                "Time spent in the function itself, not including other functions called by it, in seconds.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(self_time.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(Vec::new())),
+            descs,
+            calls,
+            total_time,
+            self_time,
+        })
+    }
+
+    // Queries the function statistics of one database, none when track_functions is off there.
+    async fn database_functions(
+        &self,
+        pool: &instance::TrackedPool,
+        top: i64,
+    ) -> anyhow::Result<Vec<PGFunctionStats>> {
+        let track_functions = sqlx::query_scalar::<_, String>(TRACK_FUNCTIONS_QUERY)
+            .fetch_one(&mut *pool.acquire().await?)
+            .await?;
+        if track_functions == "none" {
+            debug!(
+                "pg functions collector: track_functions is none in database {}",
+                pool.connect_options().get_database().unwrap_or_default()
+            );
+            return Ok(Vec::new());
+        }
+
+        let rows = if top > 0 {
+            sqlx::query_as::<_, PGFunctionStats>(USER_FUNCTIONS_QUERY_TOPK)
+                .bind(top)
+                .fetch_all(&mut *pool.acquire().await?)
+                .await?
+        } else {
+            sqlx::query_as::<_, PGFunctionStats>(USER_FUNCTIONS_QUERY)
+                .fetch_all(&mut *pool.acquire().await?)
+                .await?
+        };
+
+        Ok(rows)
+    }
+}
+
+impl Collector for PGFunctionsCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(3);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg functions collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        self.calls.reset();
+        self.total_time.reset();
+        self.self_time.reset();
+
+        for row in data_lock.iter() {
+            let labels = [
+                row.database.as_str(),
+                row.schema.as_str(),
+                row.function.as_str(),
+            ];
+
+            self.calls
+                .with_label_values(&labels)
+                .inc_by(row.calls as u64);
+            self.total_time
+                .with_label_values(&labels)
+                .inc_by(row.total_time / 1000.0);
+            self.self_time
+                .with_label_values(&labels)
+                .inc_by(row.self_time / 1000.0);
+        }
+
+        mfs.extend(self.calls.collect());
+        mfs.extend(self.total_time.collect());
+        mfs.extend(self.self_time.collect());
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGFunctionsCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+        let mut function_rows: Vec<PGFunctionStats> = self
+            .dbi
+            .query_databases("pg functions collector", |pool| async move {
+                self.database_functions(&pool, cfg.pg_collect_top_function)
+                    .await
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg functions collector: can't acquire write lock. {}", e),
+        };
+
+        data_lock.clear();
+        data_lock.append(&mut function_rows);
+
+        Ok(())
+    }
+}
@@ -11,6 +11,8 @@ use tracing::error;
 use crate::collectors::PG;
 use crate::instance;
 
+pub const NAME: &str = "pg_indexes";
+
 const USER_INDEXES_QUERY: &str = "SELECT current_database() AS database, schemaname AS schema, relname AS table,
         indexrelname AS index, (i.indisprimary OR i.indisunique) AS key,
 		i.indisvalid AS isvalid, idx_scan, idx_tup_read, idx_tup_fetch, idx_blks_read, idx_blks_hit, pg_relation_size(s1.indexrelid) AS size_bytes
@@ -171,6 +173,11 @@ impl Collector for PGIndexesCollector {
             }
         };
 
+        self.indexes.reset();
+        self.tuples.reset();
+        self.io.reset();
+        self.sizes.reset();
+
         for row in data_lock.iter() {
             // always send idx scan metrics and indexes size
             self.indexes
@@ -251,18 +258,32 @@ impl Collector for PGIndexesCollector {
 
 #[async_trait]
 impl PG for PGIndexesCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
-        let mut pg_idx_stats_rows = if cfg.pg_collect_topidx > 0 {
-            sqlx::query_as::<_, PGIndexesStats>(USER_INDEXES_QUERY_TOPK)
-                .bind(cfg.pg_collect_topidx)
-                .fetch_all(&self.dbi.db)
-                .await?
-        } else {
-            sqlx::query_as::<_, PGIndexesStats>(USER_INDEXES_QUERY)
-                .fetch_all(&self.dbi.db)
-                .await?
-        };
+
+        let mut pg_idx_stats_rows: Vec<PGIndexesStats> = self
+            .dbi
+            .query_databases("pg indexes collector", |pool| async move {
+                let rows = if cfg.pg_collect_topidx > 0 {
+                    sqlx::query_as::<_, PGIndexesStats>(USER_INDEXES_QUERY_TOPK)
+                        .bind(cfg.pg_collect_topidx)
+                        .fetch_all(&mut *pool.acquire().await?)
+                        .await?
+                } else {
+                    sqlx::query_as::<_, PGIndexesStats>(USER_INDEXES_QUERY)
+                        .fetch_all(&mut *pool.acquire().await?)
+                        .await?
+                };
+                Ok(rows)
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
 
         let mut data_lock = match self.data.write() {
             Ok(data_lock) => data_lock,
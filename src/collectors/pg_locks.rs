@@ -9,6 +9,8 @@ use tracing::error;
 use crate::collectors::PG;
 use crate::instance;
 
+pub const NAME: &str = "pg_locks";
+
 const LOCKSQUERY: &str = "SELECT  \
 		count(*) FILTER (WHERE mode = 'AccessShareLock') AS access_share_lock,  \
 		count(*) FILTER (WHERE mode = 'RowShareLock') AS row_share_lock, \
@@ -265,9 +267,13 @@ impl Collector for PGLocksCollector {
 
 #[async_trait]
 impl PG for PGLocksCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let maybe_locks_stats = sqlx::query_as::<_, LocksStat>(LOCKSQUERY)
-            .fetch_optional(&self.dbi.db)
+            .fetch_optional(&mut *self.dbi.acquire().await?)
             .await?;
 
         if let Some(locks_stats) = maybe_locks_stats {
@@ -11,6 +11,8 @@ use crate::instance;
 
 use super::PG;
 
+pub const NAME: &str = "pg_postmaster";
+
 const POSTMASTER_QUERY: &str = "SELECT extract(epoch from pg_postmaster_start_time)::FLOAT8 as start_time_seconds from pg_postmaster_start_time()";
 const POSTMASTER_SUBSYSTEM: &str = "postmaster";
 
@@ -91,9 +93,13 @@ impl Collector for PGPostmasterCollector {
 
 #[async_trait]
 impl PG for PGPostmasterCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let maybe_stats = sqlx::query_as::<_, PGPostmasterStats>(POSTMASTER_QUERY)
-            .fetch_optional(&self.dbi.db)
+            .fetch_optional(&mut *self.dbi.acquire().await?)
             .await?;
 
         if let Some(stats) = maybe_stats {
@@ -0,0 +1,308 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::IntGaugeVec;
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::proto;
+use tracing::error;
+
+use crate::collectors::{PG, POSTGRES_V12, POSTGRES_V13, POSTGRES_V17, POSTGRES_V96};
+use crate::instance;
+
+pub const NAME: &str = "pg_progress";
+
+// The progress views list commands of every database, but regclass only resolves relations of
+// the database the exporter is connected to, relations of other databases are left empty.
+
+// Progress of (auto)vacuums for Postgres 16 and older.
+const PROGRESS_VACUUM_QUERY96: &str = "SELECT pid, datname AS database,
+    CASE WHEN datname = current_database() THEN relid::regclass::text END AS relation, 'vacuum' AS command, phase,
+    heap_blks_total AS blocks_total, heap_blks_scanned AS blocks_done,
+    heap_blks_vacuumed, index_vacuum_count,
+    num_dead_tuples AS dead_tuples, max_dead_tuples,
+    NULL::bigint AS dead_tuple_bytes, NULL::bigint AS max_dead_tuple_bytes
+    FROM pg_stat_progress_vacuum";
+
+// Postgres 17 tracks dead tuples by memory usage and counts dead item identifiers.
+const PROGRESS_VACUUM_QUERY_LATEST: &str = "SELECT pid, datname AS database,
+    CASE WHEN datname = current_database() THEN relid::regclass::text END AS relation, 'vacuum' AS command, phase,
+    heap_blks_total AS blocks_total, heap_blks_scanned AS blocks_done,
+    heap_blks_vacuumed, index_vacuum_count,
+    num_dead_item_ids AS dead_tuples, NULL::bigint AS max_dead_tuples,
+    dead_tuple_bytes, max_dead_tuple_bytes
+    FROM pg_stat_progress_vacuum";
+
+// pg_stat_progress_analyze is available since Postgres 13.
+const PROGRESS_ANALYZE_QUERY: &str = "SELECT pid, datname AS database,
+    CASE WHEN datname = current_database() THEN relid::regclass::text END AS relation, 'analyze' AS command, phase,
+    sample_blks_total AS blocks_total, sample_blks_scanned AS blocks_done,
+    NULL::bigint AS heap_blks_vacuumed, NULL::bigint AS index_vacuum_count,
+    NULL::bigint AS dead_tuples, NULL::bigint AS max_dead_tuples,
+    NULL::bigint AS dead_tuple_bytes, NULL::bigint AS max_dead_tuple_bytes
+    FROM pg_stat_progress_analyze";
+
+// pg_stat_progress_create_index is available since Postgres 12, the command is one of
+// CREATE INDEX, CREATE INDEX CONCURRENTLY, REINDEX or REINDEX CONCURRENTLY.
+const PROGRESS_CREATE_INDEX_QUERY: &str = "SELECT pid, datname AS database,
+    CASE WHEN datname = current_database() THEN relid::regclass::text END AS relation, lower(replace(command, ' ', '_')) AS command, phase,
+    blocks_total, blocks_done,
+    NULL::bigint AS heap_blks_vacuumed, NULL::bigint AS index_vacuum_count,
+    NULL::bigint AS dead_tuples, NULL::bigint AS max_dead_tuples,
+    NULL::bigint AS dead_tuple_bytes, NULL::bigint AS max_dead_tuple_bytes
+    FROM pg_stat_progress_create_index";
+
+// pg_stat_progress_cluster is available since Postgres 12, the command is CLUSTER or VACUUM FULL.
+const PROGRESS_CLUSTER_QUERY: &str = "SELECT pid, datname AS database,
+    CASE WHEN datname = current_database() THEN relid::regclass::text END AS relation, lower(replace(command, ' ', '_')) AS command, phase,
+    heap_blks_total AS blocks_total, heap_blks_scanned AS blocks_done,
+    NULL::bigint AS heap_blks_vacuumed, NULL::bigint AS index_vacuum_count,
+    NULL::bigint AS dead_tuples, NULL::bigint AS max_dead_tuples,
+    NULL::bigint AS dead_tuple_bytes, NULL::bigint AS max_dead_tuple_bytes
+    FROM pg_stat_progress_cluster";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGProgressStats {
+    pid: i32,
+    database: Option<String>,
+    relation: Option<String>,
+    command: Option<String>,
+    phase: Option<String>,
+    blocks_total: Option<i64>,
+    blocks_done: Option<i64>,
+    heap_blks_vacuumed: Option<i64>,
+    index_vacuum_count: Option<i64>,
+    dead_tuples: Option<i64>,
+    max_dead_tuples: Option<i64>,
+    dead_tuple_bytes: Option<i64>,
+    max_dead_tuple_bytes: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PGProgressCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<Vec<PGProgressStats>>>,
+    descs: Vec<Desc>,
+    blocks_total: IntGaugeVec,
+    blocks_done: IntGaugeVec,
+    heap_blocks_vacuumed: IntGaugeVec,
+    index_vacuum_count: IntGaugeVec,
+    dead_tuples: IntGaugeVec,
+    max_dead_tuples: IntGaugeVec,
+    dead_tuple_bytes: IntGaugeVec,
+    max_dead_tuple_bytes: IntGaugeVec,
+}
+
+// Collecting progress of vacuums since Postgres 9.6, of other commands since they are reported.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V96
+}
+
+// Returns the progress query of all commands reported by the given Postgres version.
+fn progress_query(pg_version: i64) -> String {
+    let mut queries = vec![if pg_version < POSTGRES_V17 {
+        PROGRESS_VACUUM_QUERY96
+    } else {
+        PROGRESS_VACUUM_QUERY_LATEST
+    }];
+
+    if pg_version >= POSTGRES_V12 {
+        queries.push(PROGRESS_CREATE_INDEX_QUERY);
+        queries.push(PROGRESS_CLUSTER_QUERY);
+    }
+
+    if pg_version >= POSTGRES_V13 {
+        queries.push(PROGRESS_ANALYZE_QUERY);
+    }
+
+    queries.join(" UNION ALL ")
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGProgressCollector> {
+    match PGProgressCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg progress collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGProgressCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+        let data = Arc::new(RwLock::new(Vec::new()));
+        let label_names = ["pid", "database", "relation", "command", "phase"];
+
+        let gauge = |name: &str, help: &str| {
+            IntGaugeVec::new(
+                Opts::new(name, help)
+                    .namespace(super::NAMESPACE)
+                    .subsystem("progress")
+                    .const_labels(dbi.labels.clone()),
+                &label_names,
+            )
+        };
+
+        let blocks_total = gauge(
+            "blocks_total",
+            "Total number of blocks to process: heap blocks of vacuum and cluster, sampled blocks of analyze, blocks of the current create index phase.",
+        )?;
+        descs.extend(blocks_total.desc().into_iter().cloned());
+
+        let blocks_done = gauge(
+            "blocks_done",
+            "Number of blocks processed so far: scanned heap blocks of vacuum and cluster, scanned sample blocks of analyze, blocks of the current create index phase.",
+        )?;
+        descs.extend(blocks_done.desc().into_iter().cloned());
+
+        let heap_blocks_vacuumed = gauge(
+            "heap_blocks_vacuumed",
+            "Number of heap blocks vacuumed so far.",
+        )?;
+        descs.extend(heap_blocks_vacuumed.desc().into_iter().cloned());
+
+        let index_vacuum_count = gauge(
+            "index_vacuum_count",
+            "Number of completed index vacuum cycles.",
+        )?;
+        descs.extend(index_vacuum_count.desc().into_iter().cloned());
+
+        let dead_tuples = gauge(
+            "dead_tuples",
+            "Number of dead tuples collected since the last index vacuum cycle.",
+        )?;
+        descs.extend(dead_tuples.desc().into_iter().cloned());
+
+        let max_dead_tuples = gauge(
+            "max_dead_tuples",
+            "Number of dead tuples which fit into maintenance_work_mem before an index vacuum cycle is needed, until Postgres 16.",
+        )?;
+        descs.extend(max_dead_tuples.desc().into_iter().cloned());
+
+        let dead_tuple_bytes = gauge(
+            "dead_tuple_bytes",
+            "Memory used by dead tuples collected since the last index vacuum cycle, in bytes. Since Postgres 17.",
+        )?;
+        descs.extend(dead_tuple_bytes.desc().into_iter().cloned());
+
+        let max_dead_tuple_bytes = gauge(
+            "max_dead_tuple_bytes",
+            "Memory available for dead tuples before an index vacuum cycle is needed, in bytes. Since Postgres 17.",
+        )?;
+        descs.extend(max_dead_tuple_bytes.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data,
+            descs,
+            blocks_total,
+            blocks_done,
+            heap_blocks_vacuumed,
+            index_vacuum_count,
+            dead_tuples,
+            max_dead_tuples,
+            dead_tuple_bytes,
+            max_dead_tuple_bytes,
+        })
+    }
+}
+
+impl Collector for PGProgressCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(8);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg progress collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        let gauges = [
+            &self.blocks_total,
+            &self.blocks_done,
+            &self.heap_blocks_vacuumed,
+            &self.index_vacuum_count,
+            &self.dead_tuples,
+            &self.max_dead_tuples,
+            &self.dead_tuple_bytes,
+            &self.max_dead_tuple_bytes,
+        ];
+        for gauge in gauges {
+            gauge.reset();
+        }
+
+        for row in data_lock.iter() {
+            let pid = row.pid.to_string();
+            let labels = [
+                pid.as_str(),
+                row.database.as_deref().unwrap_or_default(),
+                row.relation.as_deref().unwrap_or_default(),
+                row.command.as_deref().unwrap_or_default(),
+                row.phase.as_deref().unwrap_or_default(),
+            ];
+
+            let values = [
+                row.blocks_total,
+                row.blocks_done,
+                row.heap_blks_vacuumed,
+                row.index_vacuum_count,
+                row.dead_tuples,
+                row.max_dead_tuples,
+                row.dead_tuple_bytes,
+                row.max_dead_tuple_bytes,
+            ];
+            // columns a command does not report are NULL, they are not exported.
+            for (gauge, value) in gauges.iter().zip(values) {
+                if let Some(value) = value {
+                    gauge.with_label_values(&labels).set(value);
+                }
+            }
+        }
+
+        for gauge in gauges {
+            mfs.extend(gauge.collect());
+        }
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGProgressCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+
+        let mut progress_rows =
+            sqlx::query_as::<_, PGProgressStats>(&progress_query(cfg.pg_version))
+                .fetch_all(&mut *self.dbi.acquire().await?)
+                .await?;
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg progress collector: can't acquire write lock. {}", e),
+        };
+
+        data_lock.clear();
+        data_lock.append(&mut progress_rows);
+
+        Ok(())
+    }
+}
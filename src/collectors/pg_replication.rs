@@ -8,10 +8,12 @@ use async_trait::async_trait;
 use crate::instance;
 use prometheus::core::{Collector, Desc, Opts};
 use prometheus::{IntGaugeVec, proto};
-use tracing::{error, info};
+use tracing::error;
 
 use crate::collectors::{PG, POSTGRES_V10, POSTGRES_V96};
 
+pub const NAME: &str = "pg_replication";
+
 // Query for Postgres version 9.6 and older.
 const POSTGRES_REPLICATION_QUERY96: &str = "SELECT pid, COALESCE(host(client_addr), '127.0.0.1') AS client_addr,
 		COALESCE(client_port, '0') AS client_port,
@@ -74,24 +76,18 @@ pub struct PGReplicationCollector {
     lag_total_seconds: IntGaugeVec,
 }
 
+// Collecting pg_replication since Postgres 9.6.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V96
+}
+
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGReplicationCollector> {
-    // Collecting pg_replication since Postgres 9.6.
-    if dbi
-        .current_cfg()
-        .map(|c| c.pg_version)
-        .unwrap_or(POSTGRES_V96)
-        >= POSTGRES_V96
-    {
-        match PGReplicationCollector::new(dbi) {
-            Ok(result) => Some(result),
-            Err(e) => {
-                error!("error when create pg replication collector: {}", e);
-                None
-            }
+    match PGReplicationCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg replication collector: {}", e);
+            None
         }
-    } else {
-        info!("some server-side functions are not available, required Postgres 9.6 or newer");
-        None
     }
 }
 
@@ -187,6 +183,11 @@ impl Collector for PGReplicationCollector {
             }
         };
 
+        self.lag_bytes.reset();
+        self.lag_seconds.reset();
+        self.lag_total_bytes.reset();
+        self.lag_total_seconds.reset();
+
         for row in data_lock.iter() {
             let pid = row.pid.unwrap_or_default();
             let client_addr = row.client_addr.clone().unwrap_or_default();
@@ -355,16 +356,24 @@ impl Collector for PGReplicationCollector {
 
 #[async_trait]
 impl PG for PGReplicationCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let mut pg_replc_stat_rows = if cfg.pg_version < POSTGRES_V10 {
             sqlx::query_as::<_, PGReplicationStats>(POSTGRES_REPLICATION_QUERY96)
                 .bind(cfg.pg_collect_topidx)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?
         } else {
             sqlx::query_as::<_, PGReplicationStats>(POSTGRES_REPLICATION_QUERY_LATEST)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?
         };
         let mut data_lock = match self.data.write() {
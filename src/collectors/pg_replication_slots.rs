@@ -8,10 +8,12 @@ use async_trait::async_trait;
 use crate::instance;
 use prometheus::core::{Collector, Desc, Opts};
 use prometheus::{IntGaugeVec, proto};
-use tracing::{error, info};
+use tracing::error;
 
 use crate::collectors::{PG, POSTGRES_V10, POSTGRES_V96};
 
+pub const NAME: &str = "pg_replication_slots";
+
 // Query for Postgres version 9.6 and older.
 const POSTGRES_REPLICATION_QUERY96: &str = "SELECT database, slot_name, slot_type, active,
 		CASE WHEN pg_is_in_recovery() THEN pg_xlog_location_diff(pg_last_xlog_receive_location(), restart_lsn)
@@ -41,24 +43,18 @@ pub struct PGReplicationSlotsCollector {
     retained_bytes: IntGaugeVec,
 }
 
+// Collecting pg_replication_slots since Postgres 9.6.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V96
+}
+
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGReplicationSlotsCollector> {
-    // Collecting pg_replication since Postgres 9.6.
-    if dbi
-        .current_cfg()
-        .map(|c| c.pg_version)
-        .unwrap_or(POSTGRES_V96)
-        >= POSTGRES_V96
-    {
-        match PGReplicationSlotsCollector::new(dbi) {
-            Ok(result) => Some(result),
-            Err(e) => {
-                error!("error when create pg replication slots collector: {}", e);
-                None
-            }
+    match PGReplicationSlotsCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg replication slots collector: {}", e);
+            None
         }
-    } else {
-        info!("some server-side functions are not available, required Postgres 9.6 or newer");
-        None
     }
 }
 
@@ -110,6 +106,8 @@ impl Collector for PGReplicationSlotsCollector {
             }
         };
 
+        self.retained_bytes.reset();
+
         for row in data_lock.iter() {
             let active = row.active.unwrap_or_default();
             let database: String = row.database.clone().unwrap_or_default().to_string();
@@ -139,16 +137,24 @@ impl Collector for PGReplicationSlotsCollector {
 
 #[async_trait]
 impl PG for PGReplicationSlotsCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let mut pg_replc_slots_stat_rows = if cfg.pg_version < POSTGRES_V10 {
             sqlx::query_as::<_, PGReplicationSlotsStats>(POSTGRES_REPLICATION_QUERY96)
                 .bind(cfg.pg_collect_topidx)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?
         } else {
             sqlx::query_as::<_, PGReplicationSlotsStats>(POSTGRES_REPLICATION_QUERY_LATEST)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?
         };
         let mut data_lock = match self.data.write() {
@@ -0,0 +1,295 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{GaugeVec, IntGaugeVec, proto};
+use tracing::error;
+
+use crate::collectors::{PG, POSTGRES_V10};
+use crate::instance;
+
+pub const NAME: &str = "pg_sequences";
+
+const SEQUENCE_SUBSYSTEM: &str = "sequence";
+
+// last_value is NULL for sequences which were never used or can't be read by the exporter.
+const SEQUENCES_QUERY: &str = "SELECT current_database() AS database, schemaname AS schema,
+    sequencename AS sequence, last_value, min_value, max_value, increment_by
+    FROM pg_sequences";
+
+// Serial and identity columns whose type can't hold the maximal value of their sequence,
+// the column overflows before the sequence is exhausted.
+const NARROW_COLUMNS_QUERY: &str =
+    "SELECT current_database() AS database, schema, \"table\", \"column\",
+    sequence, column_type, sequence_type FROM (
+    SELECT tn.nspname AS schema, t.relname AS table, a.attname AS column,
+    sn.nspname || '.' || s.relname AS sequence,
+    format_type(a.atttypid, NULL) AS column_type, format_type(seq.seqtypid, NULL) AS sequence_type,
+    seq.seqmax, CASE a.atttypid WHEN 'int2'::regtype THEN 32767 WHEN 'int4'::regtype THEN 2147483647
+    ELSE 9223372036854775807 END AS column_max
+    FROM pg_sequence seq JOIN pg_class s ON s.oid = seq.seqrelid
+    JOIN pg_namespace sn ON sn.oid = s.relnamespace
+    JOIN pg_depend d ON d.classid = 'pg_class'::regclass AND d.objid = seq.seqrelid
+    AND d.refclassid = 'pg_class'::regclass AND d.deptype IN ('a', 'i')
+    JOIN pg_class t ON t.oid = d.refobjid JOIN pg_namespace tn ON tn.oid = t.relnamespace
+    JOIN pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid) c
+    WHERE seqmax > column_max";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGSequenceStats {
+    database: String,
+    schema: String,
+    sequence: String,
+    last_value: Option<i64>,
+    min_value: i64,
+    max_value: i64,
+    increment_by: i64,
+}
+
+impl PGSequenceStats {
+    // Fraction of the values between the start and the end of the sequence already used,
+    // descending sequences count down from max_value to min_value.
+    fn used_ratio(&self, last_value: i64) -> f64 {
+        let (used, total) = if self.increment_by > 0 {
+            (
+                last_value as f64 - self.min_value as f64,
+                self.max_value as f64 - self.min_value as f64,
+            )
+        } else {
+            (
+                self.max_value as f64 - last_value as f64,
+                self.max_value as f64 - self.min_value as f64,
+            )
+        };
+
+        if total > 0.0 { used / total } else { 0.0 }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGNarrowColumn {
+    database: String,
+    schema: String,
+    table: String,
+    column: String,
+    sequence: String,
+    column_type: String,
+    sequence_type: String,
+}
+
+#[derive(Debug, Default)]
+pub struct PGSequencesStats {
+    sequences: Vec<PGSequenceStats>,
+    narrow_columns: Vec<PGNarrowColumn>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PGSequencesCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<PGSequencesStats>>,
+    descs: Vec<Desc>,
+    last_value: IntGaugeVec,
+    max_value: IntGaugeVec,
+    used_ratio: GaugeVec,
+    column_narrower: IntGaugeVec,
+}
+
+// pg_sequences and pg_sequence are available since Postgres 10.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V10
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGSequencesCollector> {
+    match PGSequencesCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg sequences collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGSequencesCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+        let sequence_labels = ["database", "schema", "sequence"];
+
+        let opts = |name: &str, help: &str| {
+            Opts::new(name, help)
+                .namespace(super::NAMESPACE)
+                .subsystem(SEQUENCE_SUBSYSTEM)
+                .const_labels(dbi.labels.clone())
+        };
+
+        let last_value = IntGaugeVec::new(
+            opts(
+                "last_value",
+                "Last value returned by the sequence, absent until it is used.",
+            ),
+            &sequence_labels,
+        )?;
+        descs.extend(last_value.desc().into_iter().cloned());
+
+        let max_value = IntGaugeVec::new(
+            opts("max_value", "Maximal value of the sequence."),
+            &sequence_labels,
+        )?;
+        descs.extend(max_value.desc().into_iter().cloned());
+
+        let used_ratio = GaugeVec::new(
+            opts(
+                "used_ratio",
+                "Fraction of the values of the sequence already used.",
+            ),
+            &sequence_labels,
+        )?;
+        descs.extend(used_ratio.desc().into_iter().cloned());
+
+        let column_narrower = IntGaugeVec::new(
+            opts(
+                "column_narrower",
+                "Serial or identity column whose type can't hold the maximal value of its sequence, always 1.",
+            ),
+            &[
+                "database",
+                "schema",
+                "table",
+                "column",
+                "sequence",
+                "column_type",
+                "sequence_type",
+            ],
+        )?;
+        descs.extend(column_narrower.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(PGSequencesStats::default())),
+            descs,
+            last_value,
+            max_value,
+            used_ratio,
+            column_narrower,
+        })
+    }
+
+    // Queries the sequences and narrow columns of one database.
+    async fn database_sequences(
+        &self,
+        pool: &instance::TrackedPool,
+    ) -> anyhow::Result<PGSequencesStats> {
+        let sequences = sqlx::query_as::<_, PGSequenceStats>(SEQUENCES_QUERY)
+            .fetch_all(&mut *pool.acquire().await?)
+            .await?;
+
+        let narrow_columns = sqlx::query_as::<_, PGNarrowColumn>(NARROW_COLUMNS_QUERY)
+            .fetch_all(&mut *pool.acquire().await?)
+            .await?;
+
+        Ok(PGSequencesStats {
+            sequences,
+            narrow_columns,
+        })
+    }
+}
+
+impl Collector for PGSequencesCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(4);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg sequences collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        self.last_value.reset();
+        self.max_value.reset();
+        self.used_ratio.reset();
+        self.column_narrower.reset();
+
+        for row in &data_lock.sequences {
+            let labels = [
+                row.database.as_str(),
+                row.schema.as_str(),
+                row.sequence.as_str(),
+            ];
+
+            self.max_value.with_label_values(&labels).set(row.max_value);
+
+            if let Some(last_value) = row.last_value {
+                self.last_value.with_label_values(&labels).set(last_value);
+                self.used_ratio
+                    .with_label_values(&labels)
+                    .set(row.used_ratio(last_value));
+            }
+        }
+
+        for row in &data_lock.narrow_columns {
+            self.column_narrower
+                .with_label_values(&[
+                    row.database.as_str(),
+                    row.schema.as_str(),
+                    row.table.as_str(),
+                    row.column.as_str(),
+                    row.sequence.as_str(),
+                    row.column_type.as_str(),
+                    row.sequence_type.as_str(),
+                ])
+                .set(1);
+        }
+
+        mfs.extend(self.last_value.collect());
+        mfs.extend(self.max_value.collect());
+        mfs.extend(self.used_ratio.collect());
+        mfs.extend(self.column_narrower.collect());
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGSequencesCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        self.dbi.ensure_ready().await?;
+
+        let mut stats = PGSequencesStats::default();
+        for mut db_stats in self
+            .dbi
+            .query_databases("pg sequences collector", |pool| async move {
+                self.database_sequences(&pool).await
+            })
+            .await?
+        {
+            stats.sequences.append(&mut db_stats.sequences);
+            stats.narrow_columns.append(&mut db_stats.narrow_columns);
+        }
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg sequences collector: can't acquire write lock. {}", e),
+        };
+
+        *data_lock = stats;
+
+        Ok(())
+    }
+}
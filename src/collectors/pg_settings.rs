@@ -0,0 +1,307 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{Gauge, IntGauge, IntGaugeVec, proto};
+use tracing::{error, warn};
+
+use crate::collectors::PG;
+use crate::instance;
+
+pub const NAME: &str = "pg_settings";
+
+const SETTINGS_SUBSYSTEM: &str = "settings";
+
+const SETTINGS_QUERY: &str =
+    "SELECT name, setting, COALESCE(unit, '') AS unit, vartype, short_desc, pending_restart
+    FROM pg_settings";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGSetting {
+    name: String,
+    setting: Option<String>,
+    unit: String,
+    vartype: String,
+    short_desc: Option<String>,
+    pending_restart: bool,
+}
+
+/// Kind of base unit a setting is converted to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BaseUnit {
+    None,
+    Bytes,
+    Seconds,
+}
+
+/// Returns the base unit of a `pg_settings` unit and the factor converting values to it.
+/// Units may carry a multiplier like `8kB` for blocks or `16MB` for WAL segments.
+fn base_unit(unit: &str) -> Option<(BaseUnit, f64)> {
+    let digits = unit.len() - unit.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (multiplier, unit) = unit.split_at(digits);
+    let multiplier = if multiplier.is_empty() {
+        1.0
+    } else {
+        multiplier.parse::<f64>().ok()?
+    };
+
+    let (base, factor) = match unit {
+        "" => (BaseUnit::None, 1.0),
+        "B" => (BaseUnit::Bytes, 1.0),
+        "kB" => (BaseUnit::Bytes, 1024.0),
+        "MB" => (BaseUnit::Bytes, 1024.0 * 1024.0),
+        "GB" => (BaseUnit::Bytes, 1024.0 * 1024.0 * 1024.0),
+        "TB" => (BaseUnit::Bytes, 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        "us" => (BaseUnit::Seconds, 0.000_001),
+        "ms" => (BaseUnit::Seconds, 0.001),
+        "s" => (BaseUnit::Seconds, 1.0),
+        "min" => (BaseUnit::Seconds, 60.0),
+        "h" => (BaseUnit::Seconds, 3600.0),
+        "d" => (BaseUnit::Seconds, 86400.0),
+        _ => return None,
+    };
+
+    Some((base, multiplier * factor))
+}
+
+impl PGSetting {
+    /// Returns the value of a numeric or boolean setting in base units with its unit.
+    /// Negative values such as `-1` usually disable a feature and are returned unconverted.
+    fn value(&self) -> Option<(f64, BaseUnit)> {
+        let setting = self.setting.as_deref()?;
+
+        match self.vartype.as_str() {
+            "bool" => Some((f64::from(u8::from(setting == "on")), BaseUnit::None)),
+            "integer" | "real" => {
+                let value = setting.parse::<f64>().ok()?;
+                let (base, factor) = base_unit(&self.unit)?;
+                if value < 0.0 {
+                    Some((value, base))
+                } else {
+                    Some((value * factor, base))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Metric names allow only ASCII letters, digits and underscores, custom settings contain a dot.
+    fn metric_name(&self) -> String {
+        self.name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PGSettingsCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<Vec<PGSetting>>>,
+    descs: Vec<Desc>,
+    pending_restart: IntGauge,
+    info: IntGaugeVec,
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGSettingsCollector> {
+    match PGSettingsCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg settings collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGSettingsCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+
+        let pending_restart = IntGauge::with_opts(
+            Opts::new(
+                "pending_restart",
+                "Whether any setting was changed in the configuration files and needs a restart to be applied.",
+            )
+            .namespace(super::NAMESPACE)
+            .subsystem(SETTINGS_SUBSYSTEM)
+            .const_labels(dbi.labels.clone()),
+        )?;
+        descs.extend(pending_restart.desc().into_iter().cloned());
+
+        let info = IntGaugeVec::new(
+            Opts::new(
+                "info",
+                "Current value of the settings listed in settings_info, always 1.",
+            )
+            .namespace(super::NAMESPACE)
+            .subsystem(SETTINGS_SUBSYSTEM)
+            .const_labels(dbi.labels.clone()),
+            &["name", "setting"],
+        )?;
+        descs.extend(info.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(Vec::new())),
+            descs,
+            pending_restart,
+            info,
+        })
+    }
+
+    // Settings are only known once queried, their gauges are created on every collect.
+    fn setting_gauge(&self, setting: &PGSetting, value: f64, base: BaseUnit) -> Option<Gauge> {
+        let mut help = setting
+            .short_desc
+            .clone()
+            .unwrap_or_else(|| setting.name.clone());
+        match base {
+            BaseUnit::Bytes => help.push_str(" In bytes."),
+            BaseUnit::Seconds => help.push_str(" In seconds."),
+            BaseUnit::None => (),
+        }
+
+        let opts = Opts::new(setting.metric_name(), help)
+            .namespace(super::NAMESPACE)
+            .subsystem(SETTINGS_SUBSYSTEM)
+            .const_labels(self.dbi.labels.clone());
+
+        match Gauge::with_opts(opts) {
+            Ok(gauge) => {
+                gauge.set(value);
+                Some(gauge)
+            }
+            Err(e) => {
+                warn!("pg settings collect: setting {}: {}", setting.name, e);
+                None
+            }
+        }
+    }
+}
+
+impl Collector for PGSettingsCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg settings collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return Vec::new();
+            }
+        };
+
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(data_lock.len() + 2);
+
+        self.info.reset();
+        self.pending_restart
+            .set(i64::from(data_lock.iter().any(|s| s.pending_restart)));
+
+        let info_names = self.dbi.settings_info();
+        for setting in data_lock.iter() {
+            if info_names.contains(&setting.name) {
+                self.info
+                    .with_label_values(&[
+                        setting.name.as_str(),
+                        setting.setting.as_deref().unwrap_or_default(),
+                    ])
+                    .set(1);
+            }
+
+            if let Some((value, base)) = setting.value()
+                && let Some(gauge) = self.setting_gauge(setting, value, base)
+            {
+                mfs.extend(gauge.collect());
+            }
+        }
+
+        mfs.extend(self.pending_restart.collect());
+        mfs.extend(self.info.collect());
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGSettingsCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        // the queried columns exist in all supported versions, the cached config only
+        // tells whether the instance is ready.
+        self.dbi.ensure_ready().await?;
+
+        let mut settings = sqlx::query_as::<_, PGSetting>(SETTINGS_QUERY)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg settings collector: can't acquire write lock. {}", e),
+        };
+
+        data_lock.clear();
+        data_lock.append(&mut settings);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(name: &str, setting: &str, unit: &str, vartype: &str) -> PGSetting {
+        PGSetting {
+            name: name.to_string(),
+            setting: Some(setting.to_string()),
+            unit: unit.to_string(),
+            vartype: vartype.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn settings_converted_to_base_units() {
+        let value = |s: PGSetting| s.value();
+
+        assert_eq!(
+            value(setting("shared_buffers", "16384", "8kB", "integer")),
+            Some((134_217_728.0, BaseUnit::Bytes))
+        );
+        assert_eq!(
+            value(setting("max_wal_size", "1024", "MB", "integer")),
+            Some((1_073_741_824.0, BaseUnit::Bytes))
+        );
+        assert_eq!(
+            value(setting("checkpoint_timeout", "5", "min", "integer")),
+            Some((300.0, BaseUnit::Seconds))
+        );
+        assert_eq!(
+            value(setting("autovacuum_vacuum_cost_delay", "2", "ms", "real")),
+            Some((0.002, BaseUnit::Seconds))
+        );
+        assert_eq!(
+            value(setting("log_min_duration_statement", "-1", "ms", "integer")),
+            Some((-1.0, BaseUnit::Seconds))
+        );
+        assert_eq!(
+            value(setting("fsync", "on", "", "bool")),
+            Some((1.0, BaseUnit::None))
+        );
+        assert_eq!(value(setting("wal_level", "replica", "", "enum")), None);
+
+        assert_eq!(
+            setting("auto_explain.log_min_duration", "-1", "ms", "integer").metric_name(),
+            "auto_explain_log_min_duration"
+        );
+    }
+}
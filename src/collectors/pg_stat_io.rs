@@ -11,6 +11,8 @@ use tracing::error;
 use crate::collectors::{PG, POSTGRES_V16, POSTGRES_V18};
 use crate::instance;
 
+pub const NAME: &str = "pg_stat_io";
+
 const POSTGRES_STAT_IO_QUERY17: &str = "SELECT backend_type, object, context, COALESCE(reads, 0) AS reads, COALESCE(read_time, 0) AS read_time,
 		COALESCE(writes, 0) AS writes, COALESCE(write_time, 0) AS write_time, COALESCE(writebacks, 0) AS writebacks,
 		COALESCE(writeback_time, 0) AS writeback_time, COALESCE(extends, 0) AS extends, COALESCE(extend_time, 0) AS extend_time,
@@ -104,23 +106,18 @@ pub struct PGStatIOCollector {
     extend_bytes: IntGaugeVec,
 }
 
+// Collecting pg_stat_io since Postgres 16.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V16
+}
+
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGStatIOCollector> {
-    // Collecting pg_stat_io since Postgres 16.
-    if dbi
-        .current_cfg()
-        .map(|c| c.pg_version)
-        .unwrap_or(POSTGRES_V16)
-        >= POSTGRES_V16
-    {
-        match PGStatIOCollector::new(dbi) {
-            Ok(result) => Some(result),
-            Err(e) => {
-                error!("error when create pg statio collector: {}", e);
-                None
-            }
+    match PGStatIOCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg statio collector: {}", e);
+            None
         }
-    } else {
-        None
     }
 }
 
@@ -355,6 +352,23 @@ impl Collector for PGStatIOCollector {
             }
         };
 
+        self.reads.reset();
+        self.read_time.reset();
+        self.writes.reset();
+        self.write_time.reset();
+        self.write_backs.reset();
+        self.writeback_time.reset();
+        self.extends.reset();
+        self.extend_time.reset();
+        self.hits.reset();
+        self.evictions.reset();
+        self.reuses.reset();
+        self.fsyncs.reset();
+        self.fsync_time.reset();
+        self.read_bytes.reset();
+        self.write_bytes.reset();
+        self.extend_bytes.reset();
+
         for row in data_lock.iter() {
             let vals = vec![
                 row.backend_type.as_str(),
@@ -431,15 +445,23 @@ impl Collector for PGStatIOCollector {
 
 #[async_trait]
 impl PG for PGStatIOCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let mut pg_statio_stats_rows = if cfg.pg_version < POSTGRES_V18 {
             sqlx::query_as::<_, PGStatIOStats>(POSTGRES_STAT_IO_QUERY17)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?
         } else {
             sqlx::query_as::<_, PGStatIOStats>(POSTGRES_STAT_IO_LATEST)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?
         };
 
@@ -11,6 +11,8 @@ use prometheus::{IntGaugeVec, proto};
 use rust_decimal::Decimal;
 use rust_decimal::prelude::ToPrimitive;
 
+pub const NAME: &str = "pg_statements";
+
 // defines query for querying statements metrics for PG12 and older.
 macro_rules! statements_query12 {
 () =>  {
@@ -583,22 +585,18 @@ impl PGStatementsCollector {
     }
 }
 
+// Collecting since Postgres 12, when pg_stat_statements is installed.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V12 && cfg.pg_stat_statements
+}
+
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGStatementsCollector> {
-    // Collecting since Postgres 12.
-    if dbi
-        .current_cfg()
-        .map(|c| c.pg_version >= POSTGRES_V12 && c.pg_stat_statements)
-        .unwrap_or(true)
-    {
-        match PGStatementsCollector::new(dbi) {
-            Ok(result) => Some(result),
-            Err(e) => {
-                error!("error when create pg statements collector: {}", e);
-                None
-            }
+    match PGStatementsCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg statements collector: {}", e);
+            None
         }
-    } else {
-        None
     }
 }
 
@@ -627,6 +625,25 @@ impl Collector for PGStatementsCollector {
             }
         };
 
+        self.query.reset();
+        self.calls.reset();
+        self.rows.reset();
+        self.times.reset();
+        self.all_times.reset();
+        self.shared_hit.reset();
+        self.shared_read.reset();
+        self.shared_dirtied.reset();
+        self.shared_written.reset();
+        self.local_hit.reset();
+        self.local_read.reset();
+        self.local_dirtied.reset();
+        self.local_written.reset();
+        self.temp_read.reset();
+        self.temp_written.reset();
+        self.wal_records.reset();
+        self.wal_buffers.reset();
+        self.wal_all_bytes.reset();
+
         for row in data_lock.iter() {
             let q = match row.query.as_ref() {
                 Some(q) => q,
@@ -922,6 +939,14 @@ impl Collector for PGStatementsCollector {
 }
 #[async_trait]
 impl PG for PGStatementsCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         if !cfg.pg_stat_statements {
@@ -932,7 +957,7 @@ impl PG for PGStatementsCollector {
 
         let mut pg_statemnts_rows = sqlx::query_as::<_, PGStatementsStat>(&query)
             .bind(cfg.pg_collect_topq)
-            .fetch_all(&self.dbi.db)
+            .fetch_all(&mut *self.dbi.acquire().await?)
             .await?;
 
         let mut data_lock = match self.data.write() {
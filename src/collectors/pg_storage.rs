@@ -8,13 +8,15 @@ use async_trait::async_trait;
 
 use prometheus::core::{Collector, Desc, Opts};
 use prometheus::{IntGaugeVec, proto};
-use tracing::{error, info};
+use tracing::error;
 
 use crate::collectors::{PG, POSTGRES_V10, POSTGRES_V12};
 use crate::instance;
 
 use sqlx::Row;
 
+pub const NAME: &str = "pg_storage";
+
 const POSTGRES_TEMP_FILES_INFLIGHT: &str = "SELECT ts.spcname AS tablespace, COALESCE(COUNT(size), 0) AS files_total, COALESCE(sum(size), 0) AS bytes_total,
 		COALESCE(EXTRACT(EPOCH FROM clock_timestamp() - min(modification)), 0) AS max_age_seconds
 		FROM pg_tablespace ts LEFT JOIN (SELECT spcname,(pg_ls_tmpdir(oid)).* FROM pg_tablespace WHERE spcname != 'pg_global') ls ON ls.spcname = ts.spcname
@@ -52,24 +54,18 @@ pub struct PGStorageCollector {
     tmp_files_bytes: IntGaugeVec,
 }
 
+// Collecting pg_storage since Postgres 10.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V10
+}
+
 pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGStorageCollector> {
-    // Collecting pg_storage since Postgres 10.
-    if dbi
-        .current_cfg()
-        .map(|c| c.pg_version)
-        .unwrap_or(POSTGRES_V10)
-        >= POSTGRES_V10
-    {
-        match PGStorageCollector::new(dbi) {
-            Ok(result) => Some(result),
-            Err(e) => {
-                error!("error when create pg storage collector: {}", e);
-                None
-            }
+    match PGStorageCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg storage collector: {}", e);
+            None
         }
-    } else {
-        info!("some server-side functions are not available, required Postgres 10 or newer");
-        None
     }
 }
 
@@ -185,6 +181,13 @@ impl Collector for PGStorageCollector {
             }
         };
 
+        self.temp_files.reset();
+        self.temp_bytes.reset();
+        self.temp_files_max_age.reset();
+        self.wal_dir_bytes.reset();
+        self.wal_dir_files.reset();
+        self.tmp_files_bytes.reset();
+
         let dirstat_lock = match self.data_dirstat.read() {
             Ok(lock) => lock,
             Err(e) => {
@@ -264,16 +267,24 @@ impl Collector for PGStorageCollector {
 
 #[async_trait]
 impl PG for PGStorageCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let mut pg_storage_stat_rows =
             sqlx::query_as::<_, PGStorageStats>(POSTGRES_TEMP_FILES_INFLIGHT)
                 .bind(cfg.pg_collect_topidx)
-                .fetch_all(&self.dbi.db)
+                .fetch_all(&mut *self.dbi.acquire().await?)
                 .await?;
 
         let waldir_row = sqlx::query("SELECT current_setting('data_directory')||'/pg_wal' AS path, COALESCE(sum(size), 0) AS bytes, COALESCE(count(name), 0) AS count FROM pg_ls_waldir()")
-            .fetch_one(&self.dbi.db)
+            .fetch_one(&mut *self.dbi.acquire().await?)
             .await?;
 
         let wal_path: String = waldir_row.try_get("path")?;
@@ -281,7 +292,7 @@ impl PG for PGStorageCollector {
         let wal_count: i64 = waldir_row.try_get("count")?;
 
         let tmpdir_row = sqlx::query("SELECT coalesce(sum(size), 0) AS bytes, coalesce(count(name), 0) AS count FROM (SELECT (pg_ls_tmpdir(oid)).* FROM pg_tablespace WHERE spcname != 'pg_global') tablespaces")
-        .fetch_one(&self.dbi.db)
+        .fetch_one(&mut *self.dbi.acquire().await?)
         .await?;
 
         let tmpdir_bytes: Decimal = tmpdir_row.try_get("bytes")?;
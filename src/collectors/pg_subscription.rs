@@ -0,0 +1,403 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{GaugeVec, IntCounterVec, IntGaugeVec, proto};
+use tracing::error;
+
+use crate::collectors::{PG, POSTGRES_V10, POSTGRES_V15, POSTGRES_V16, POSTGRES_V17};
+use crate::instance;
+
+pub const NAME: &str = "pg_subscription";
+
+const SUBSCRIPTION_SUBSYSTEM: &str = "subscription";
+
+// Query for Postgres versions from 10 to 14, the apply worker is the only worker without a relation.
+const SUBSCRIPTION_QUERY14: &str =
+    "SELECT d.datname AS database, s.subname AS subscription, s.subenabled AS enabled,
+    (w.received_lsn - w.latest_end_lsn)::FLOAT8 AS lag_bytes,
+    EXTRACT(EPOCH FROM w.latest_end_time)::FLOAT8 AS latest_end_time,
+    NULL::INT8 AS apply_error_count, NULL::INT8 AS sync_error_count
+    FROM pg_subscription s JOIN pg_database d ON d.oid = s.subdbid
+    LEFT JOIN pg_stat_subscription w ON w.subid = s.oid AND w.relid IS NULL";
+
+// Query for Postgres version 15, error counts are available in pg_stat_subscription_stats.
+const SUBSCRIPTION_QUERY15: &str =
+    "SELECT d.datname AS database, s.subname AS subscription, s.subenabled AS enabled,
+    (w.received_lsn - w.latest_end_lsn)::FLOAT8 AS lag_bytes,
+    EXTRACT(EPOCH FROM w.latest_end_time)::FLOAT8 AS latest_end_time,
+    st.apply_error_count, st.sync_error_count
+    FROM pg_subscription s JOIN pg_database d ON d.oid = s.subdbid
+    LEFT JOIN pg_stat_subscription w ON w.subid = s.oid AND w.relid IS NULL
+    LEFT JOIN pg_stat_subscription_stats st ON st.subid = s.oid";
+
+// Query for Postgres versions from 16 and newer, parallel apply workers have a leader.
+const SUBSCRIPTION_QUERY_LATEST: &str =
+    "SELECT d.datname AS database, s.subname AS subscription, s.subenabled AS enabled,
+    (w.received_lsn - w.latest_end_lsn)::FLOAT8 AS lag_bytes,
+    EXTRACT(EPOCH FROM w.latest_end_time)::FLOAT8 AS latest_end_time,
+    st.apply_error_count, st.sync_error_count
+    FROM pg_subscription s JOIN pg_database d ON d.oid = s.subdbid
+    LEFT JOIN pg_stat_subscription w ON w.subid = s.oid AND w.relid IS NULL AND w.leader_pid IS NULL
+    LEFT JOIN pg_stat_subscription_stats st ON st.subid = s.oid";
+
+// Query for Postgres versions from 10 to 15, subscriptions without workers are listed with a NULL pid.
+const WORKERS_QUERY15: &str = "SELECT d.datname AS database, w.subname AS subscription,
+    CASE WHEN w.relid IS NULL THEN 'apply' ELSE 'table synchronization' END AS worker_type,
+    count(*) AS workers
+    FROM pg_stat_subscription w JOIN pg_subscription s ON s.oid = w.subid
+    JOIN pg_database d ON d.oid = s.subdbid
+    WHERE w.pid IS NOT NULL GROUP BY 1, 2, 3";
+
+// Query for Postgres version 16.
+const WORKERS_QUERY16: &str = "SELECT d.datname AS database, w.subname AS subscription,
+    CASE WHEN w.relid IS NOT NULL THEN 'table synchronization'
+    WHEN w.leader_pid IS NOT NULL THEN 'parallel apply' ELSE 'apply' END AS worker_type,
+    count(*) AS workers
+    FROM pg_stat_subscription w JOIN pg_subscription s ON s.oid = w.subid
+    JOIN pg_database d ON d.oid = s.subdbid
+    WHERE w.pid IS NOT NULL GROUP BY 1, 2, 3";
+
+// Query for Postgres versions from 17 and newer.
+const WORKERS_QUERY_LATEST: &str = "SELECT d.datname AS database, w.subname AS subscription,
+    w.worker_type, count(*) AS workers
+    FROM pg_stat_subscription w JOIN pg_subscription s ON s.oid = w.subid
+    JOIN pg_database d ON d.oid = s.subdbid
+    WHERE w.pid IS NOT NULL GROUP BY 1, 2, 3";
+
+// pg_subscription_rel is local to the database of the subscription.
+const TABLES_QUERY: &str = "SELECT current_database() AS database, s.subname AS subscription,
+    n.nspname AS schema, c.relname AS table, r.srsubstate::text AS state
+    FROM pg_subscription_rel r JOIN pg_subscription s ON s.oid = r.srsubid
+    JOIN pg_class c ON c.oid = r.srrelid JOIN pg_namespace n ON n.oid = c.relnamespace";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGSubscriptionStats {
+    database: String,
+    subscription: String,
+    enabled: bool,
+    // NULL when the apply worker is not running.
+    lag_bytes: Option<f64>,
+    latest_end_time: Option<f64>,
+    apply_error_count: Option<i64>,
+    sync_error_count: Option<i64>,
+}
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGSubscriptionWorkers {
+    database: String,
+    subscription: String,
+    worker_type: String,
+    workers: i64,
+}
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGSubscriptionTable {
+    database: String,
+    subscription: String,
+    schema: String,
+    table: String,
+    state: String,
+}
+
+impl PGSubscriptionTable {
+    // Names of the srsubstate codes as documented for pg_subscription_rel.
+    fn state_name(&self) -> &str {
+        match self.state.as_str() {
+            "i" => "init",
+            "d" => "data_copy",
+            "f" => "finished_copy",
+            "s" => "synchronized",
+            "r" => "ready",
+            state => state,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PGSubscriptionsStats {
+    subscriptions: Vec<PGSubscriptionStats>,
+    workers: Vec<PGSubscriptionWorkers>,
+    tables: Vec<PGSubscriptionTable>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PGSubscriptionCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<PGSubscriptionsStats>>,
+    descs: Vec<Desc>,
+    enabled: IntGaugeVec,
+    workers: IntGaugeVec,
+    lag_bytes: GaugeVec,
+    latest_end_time: GaugeVec,
+    apply_errors: IntCounterVec,
+    sync_errors: IntCounterVec,
+    table_state: IntGaugeVec,
+}
+
+// Logical replication is available since Postgres 10.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V10
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGSubscriptionCollector> {
+    match PGSubscriptionCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg subscription collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGSubscriptionCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+        let label_names = ["database", "subscription"];
+
+        let opts = |name: &str, help: &str| {
+            Opts::new(name, help)
+                .namespace(super::NAMESPACE)
+                .subsystem(SUBSCRIPTION_SUBSYSTEM)
+                .const_labels(dbi.labels.clone())
+        };
+
+        let enabled = IntGaugeVec::new(
+            opts(
+                "enabled",
+                "Whether the subscription is enabled, 1 - enabled; 0 - disabled.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(enabled.desc().into_iter().cloned());
+
+        let workers = IntGaugeVec::new(
+            opts(
+                "workers",
+                "Number of running subscription workers by worker type.",
+            ),
+            &["database", "subscription", "worker_type"],
+        )?;
+        descs.extend(workers.desc().into_iter().cloned());
+
+        let lag_bytes = GaugeVec::new(
+            opts(
+                "lag_bytes",
+                "Amount of WAL received by the apply worker but not yet reported to the publisher, in bytes.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(lag_bytes.desc().into_iter().cloned());
+
+        let latest_end_time = GaugeVec::new(
+            opts(
+                "latest_end_time_seconds",
+                "Time of the last WAL location reported to the publisher by the apply worker, in unixtime.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(latest_end_time.desc().into_iter().cloned());
+
+        let apply_errors = IntCounterVec::new(
+            opts(
+                "apply_errors_total",
+                "Total number of errors while applying changes of the subscription.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(apply_errors.desc().into_iter().cloned());
+
+        let sync_errors = IntCounterVec::new(
+            opts(
+                "sync_errors_total",
+                "Total number of errors during the initial table synchronization of the subscription.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(sync_errors.desc().into_iter().cloned());
+
+        let table_state = IntGaugeVec::new(
+            opts(
+                "table_state",
+                "Synchronization state of each table of the subscription, always 1.",
+            ),
+            &["database", "subscription", "schema", "table", "state"],
+        )?;
+        descs.extend(table_state.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(PGSubscriptionsStats::default())),
+            descs,
+            enabled,
+            workers,
+            lag_bytes,
+            latest_end_time,
+            apply_errors,
+            sync_errors,
+            table_state,
+        })
+    }
+
+    // Queries the tables of the subscriptions created in one database.
+    async fn database_tables(
+        &self,
+        pool: &instance::TrackedPool,
+    ) -> anyhow::Result<Vec<PGSubscriptionTable>> {
+        let tables = sqlx::query_as::<_, PGSubscriptionTable>(TABLES_QUERY)
+            .fetch_all(&mut *pool.acquire().await?)
+            .await?;
+
+        Ok(tables)
+    }
+}
+
+impl Collector for PGSubscriptionCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(7);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg subscription collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        self.enabled.reset();
+        self.workers.reset();
+        self.lag_bytes.reset();
+        self.latest_end_time.reset();
+        self.apply_errors.reset();
+        self.sync_errors.reset();
+        self.table_state.reset();
+
+        for row in &data_lock.subscriptions {
+            let labels = [row.database.as_str(), row.subscription.as_str()];
+
+            self.enabled
+                .with_label_values(&labels)
+                .set(i64::from(row.enabled));
+
+            if let Some(lag_bytes) = row.lag_bytes {
+                self.lag_bytes.with_label_values(&labels).set(lag_bytes);
+            }
+            if let Some(latest_end_time) = row.latest_end_time {
+                self.latest_end_time
+                    .with_label_values(&labels)
+                    .set(latest_end_time);
+            }
+            if let Some(apply_error_count) = row.apply_error_count {
+                self.apply_errors
+                    .with_label_values(&labels)
+                    .inc_by(apply_error_count as u64);
+            }
+            if let Some(sync_error_count) = row.sync_error_count {
+                self.sync_errors
+                    .with_label_values(&labels)
+                    .inc_by(sync_error_count as u64);
+            }
+        }
+
+        for row in &data_lock.workers {
+            self.workers
+                .with_label_values(&[
+                    row.database.as_str(),
+                    row.subscription.as_str(),
+                    row.worker_type.as_str(),
+                ])
+                .set(row.workers);
+        }
+
+        for row in &data_lock.tables {
+            self.table_state
+                .with_label_values(&[
+                    row.database.as_str(),
+                    row.subscription.as_str(),
+                    row.schema.as_str(),
+                    row.table.as_str(),
+                    row.state_name(),
+                ])
+                .set(1);
+        }
+
+        mfs.extend(self.enabled.collect());
+        mfs.extend(self.workers.collect());
+        mfs.extend(self.lag_bytes.collect());
+        mfs.extend(self.latest_end_time.collect());
+        mfs.extend(self.apply_errors.collect());
+        mfs.extend(self.sync_errors.collect());
+        mfs.extend(self.table_state.collect());
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGSubscriptionCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+
+        // pg_subscription and pg_stat_subscription list the subscriptions of all databases.
+        let subscription_query = if cfg.pg_version < POSTGRES_V15 {
+            SUBSCRIPTION_QUERY14
+        } else if cfg.pg_version < POSTGRES_V16 {
+            SUBSCRIPTION_QUERY15
+        } else {
+            SUBSCRIPTION_QUERY_LATEST
+        };
+        let subscriptions = sqlx::query_as::<_, PGSubscriptionStats>(subscription_query)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let workers_query = if cfg.pg_version < POSTGRES_V16 {
+            WORKERS_QUERY15
+        } else if cfg.pg_version < POSTGRES_V17 {
+            WORKERS_QUERY16
+        } else {
+            WORKERS_QUERY_LATEST
+        };
+        let workers = sqlx::query_as::<_, PGSubscriptionWorkers>(workers_query)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let tables = self
+            .dbi
+            .query_databases("pg subscription collector", |pool| async move {
+                self.database_tables(&pool).await
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg subscription collector: can't acquire write lock. {}", e),
+        };
+
+        *data_lock = PGSubscriptionsStats {
+            subscriptions,
+            workers,
+            tables,
+        };
+
+        Ok(())
+    }
+}
@@ -13,6 +13,8 @@ use tracing::error;
 use crate::collectors::PG;
 use crate::instance;
 
+pub const NAME: &str = "pg_tables";
+
 const POSTGRES_USERS_TABLE: &str = "SELECT current_database() AS database, s1.schemaname AS schema, s1.relname AS table,
 		seq_scan, seq_tup_read, idx_scan, idx_tup_fetch, n_tup_ins, n_tup_upd, n_tup_del, n_tup_hot_upd,
 		n_live_tup, n_dead_tup, n_mod_since_analyze,
@@ -418,6 +420,26 @@ impl Collector for PGTableCollector {
             }
         };
 
+        self.seqscan.reset();
+        self.seqtupread.reset();
+        self.idxscan.reset();
+        self.idxtupfetch.reset();
+        self.tup_inserted.reset();
+        self.tup_updated.reset();
+        self.tup_hot_updated.reset();
+        self.tup_deleted.reset();
+        self.tup_live.reset();
+        self.tup_dead.reset();
+        self.tup_modified.reset();
+        self.maint_last_vacuum_age.reset();
+        self.maint_last_analyze_age.reset();
+        self.maint_last_vacuum_time.reset();
+        self.maint_last_analyze_time.reset();
+        self.maintenance.reset();
+        self.io.reset();
+        self.sizes.reset();
+        self.reltuples.reset();
+
         for row in data_lock.iter() {
             // scan stats
             self.seqscan
@@ -831,18 +853,32 @@ impl Collector for PGTableCollector {
 
 #[async_trait]
 impl PG for PGTableCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
-        let mut pg_tables_stat_rows = if cfg.pg_collect_top_table > 0 {
-            sqlx::query_as::<_, PGTablesStats>(POSTGRES_USERS_TABLE_TOPK)
-                .bind(cfg.pg_collect_topidx)
-                .fetch_all(&self.dbi.db)
-                .await?
-        } else {
-            sqlx::query_as::<_, PGTablesStats>(POSTGRES_USERS_TABLE)
-                .fetch_all(&self.dbi.db)
-                .await?
-        };
+
+        let mut pg_tables_stat_rows: Vec<PGTablesStats> = self
+            .dbi
+            .query_databases("pg tables collector", |pool| async move {
+                let rows = if cfg.pg_collect_top_table > 0 {
+                    sqlx::query_as::<_, PGTablesStats>(POSTGRES_USERS_TABLE_TOPK)
+                        .bind(cfg.pg_collect_top_table)
+                        .fetch_all(&mut *pool.acquire().await?)
+                        .await?
+                } else {
+                    sqlx::query_as::<_, PGTablesStats>(POSTGRES_USERS_TABLE)
+                        .fetch_all(&mut *pool.acquire().await?)
+                        .await?
+                };
+                Ok(rows)
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
 
         let mut data_lock = match self.data.write() {
             Ok(data_lock) => data_lock,
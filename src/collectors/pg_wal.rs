@@ -11,6 +11,8 @@ use tracing::error;
 use crate::collectors::{PG, POSTGRES_V10, POSTGRES_V14, POSTGRES_V18};
 use crate::instance;
 
+pub const NAME: &str = "pg_wal";
+
 const POSTGRES_WAL_QUERY96: &str =
     "SELECT pg_is_in_recovery()::int AS recovery,
 		((CASE pg_is_in_recovery() WHEN 't' THEN pg_last_xlog_receive_location() ELSE pg_current_xlog_location() END) - '0/00000000')::FLOAT8 AS wal_written";
@@ -247,6 +249,17 @@ impl Collector for PGWALCollector {
             }
         };
 
+        // counters are fed with the absolute values of the server, reset them before adding.
+        self.records_total.reset();
+        self.buffers_full_total.reset();
+        self.bytes_total.reset();
+        self.fpi_total.reset();
+        self.seconds_all_total.reset();
+        self.seconds_total.reset();
+        self.sync_total.reset();
+        self.write_total.reset();
+        self.written_bytes_total.reset();
+
         self.recovery_info.set(data_lock.recovery as i64);
         self.records_total.inc_by(data_lock.wal_records as u64);
         self.buffers_full_total
@@ -284,23 +297,27 @@ impl Collector for PGWALCollector {
 
 #[async_trait]
 impl PG for PGWALCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
     async fn update(&self) -> Result<(), anyhow::Error> {
         let cfg = self.dbi.ensure_ready().await?;
         let maybe_pg_wal_stats = if cfg.pg_version < POSTGRES_V10 {
             sqlx::query_as::<_, PGWALStats>(POSTGRES_WAL_QUERY96)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         } else if cfg.pg_version < POSTGRES_V14 {
             sqlx::query_as::<_, PGWALStats>(POSTGRES_WAL_QUERY13)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         } else if cfg.pg_version < POSTGRES_V18 {
             sqlx::query_as::<_, PGWALStats>(POSTGRES_WAL_QUERY17)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         } else {
             sqlx::query_as::<_, PGWALStats>(POSTGRES_WAL_QUERY_LATEST)
-                .fetch_optional(&self.dbi.db)
+                .fetch_optional(&mut *self.dbi.acquire().await?)
                 .await?
         };
 
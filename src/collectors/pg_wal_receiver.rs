@@ -0,0 +1,295 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::{Gauge, GaugeVec, IntGauge, IntGaugeVec, proto};
+use tracing::error;
+
+use crate::collectors::{PG, POSTGRES_V10, POSTGRES_V11, POSTGRES_V13, POSTGRES_V96};
+use crate::instance;
+
+pub const NAME: &str = "pg_wal_receiver";
+
+const WAL_RECEIVER_SUBSYSTEM: &str = "wal_receiver";
+const RECOVERY_SUBSYSTEM: &str = "recovery";
+
+// The replay delay is zero when everything received is replayed, otherwise an idle primary
+// would look like a lagging standby.
+const RECOVERY_QUERY96: &str = "SELECT pg_is_in_recovery() AS is_standby,
+    (CASE WHEN NOT pg_is_in_recovery() THEN NULL
+    WHEN pg_last_xlog_receive_location() = pg_last_xlog_replay_location() THEN 0
+    ELSE EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) END)::FLOAT8 AS replay_delay_seconds";
+
+const RECOVERY_QUERY_LATEST: &str = "SELECT pg_is_in_recovery() AS is_standby,
+    (CASE WHEN NOT pg_is_in_recovery() THEN NULL
+    WHEN pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0
+    ELSE EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) END)::FLOAT8 AS replay_delay_seconds";
+
+// Query for Postgres versions 9.6 and 10, the sender host is only part of conninfo.
+// received_lsn is the position already flushed to disk.
+const WAL_RECEIVER_QUERY10: &str = "SELECT status, NULL::text AS sender_host, slot_name,
+    (received_lsn - '0/00000000')::FLOAT8 AS received_lsn, (received_lsn - '0/00000000')::FLOAT8 AS flushed_lsn,
+    EXTRACT(EPOCH FROM latest_end_time)::FLOAT8 AS latest_end_time
+    FROM pg_stat_wal_receiver";
+
+// Query for Postgres versions 11 and 12.
+const WAL_RECEIVER_QUERY12: &str = "SELECT status, sender_host, slot_name,
+    (received_lsn - '0/00000000')::FLOAT8 AS received_lsn, (received_lsn - '0/00000000')::FLOAT8 AS flushed_lsn,
+    EXTRACT(EPOCH FROM latest_end_time)::FLOAT8 AS latest_end_time
+    FROM pg_stat_wal_receiver";
+
+// Query for Postgres versions from 13 and newer, received_lsn was split into written_lsn and flushed_lsn.
+const WAL_RECEIVER_QUERY_LATEST: &str = "SELECT status, sender_host, slot_name,
+    (written_lsn - '0/00000000')::FLOAT8 AS received_lsn, (flushed_lsn - '0/00000000')::FLOAT8 AS flushed_lsn,
+    EXTRACT(EPOCH FROM latest_end_time)::FLOAT8 AS latest_end_time
+    FROM pg_stat_wal_receiver";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGRecoveryStats {
+    is_standby: bool,
+    replay_delay_seconds: Option<f64>,
+}
+
+// Columns besides status are NULL for roles without pg_read_all_stats.
+#[derive(sqlx::FromRow, Debug, Default)]
+pub struct PGWalReceiverStats {
+    status: Option<String>,
+    sender_host: Option<String>,
+    slot_name: Option<String>,
+    received_lsn: Option<f64>,
+    flushed_lsn: Option<f64>,
+    latest_end_time: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct PGStandbyStats {
+    recovery: PGRecoveryStats,
+    // no row on a primary or when the WAL receiver is not running.
+    wal_receiver: Option<PGWalReceiverStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PGWalReceiverCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<PGStandbyStats>>,
+    descs: Vec<Desc>,
+    is_standby: IntGauge,
+    replay_delay: Gauge,
+    status: IntGaugeVec,
+    received_lsn: GaugeVec,
+    flushed_lsn: GaugeVec,
+    latest_end_time: GaugeVec,
+}
+
+// pg_stat_wal_receiver is available since Postgres 9.6.
+fn supported(cfg: &instance::PGConfig) -> bool {
+    cfg.pg_version >= POSTGRES_V96
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGWalReceiverCollector> {
+    match PGWalReceiverCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg wal receiver collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGWalReceiverCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+        let label_names = ["sender_host", "slot_name"];
+
+        let opts = |subsystem: &str, name: &str, help: &str| {
+            Opts::new(name, help)
+                .namespace(super::NAMESPACE)
+                .subsystem(subsystem)
+                .const_labels(dbi.labels.clone())
+        };
+
+        let is_standby = IntGauge::with_opts(opts(
+            RECOVERY_SUBSYSTEM,
+            "is_standby",
+            "Whether the server is a standby in recovery, 1 - standby; 0 - primary. Same value as pg_recovery_info of the pg_wal collector.",
+        ))?;
+        descs.extend(is_standby.desc().into_iter().cloned());
+
+        let replay_delay = Gauge::with_opts(opts(
+            RECOVERY_SUBSYSTEM,
+            "replay_delay_seconds",
+            "Time since the last replayed transaction when not all received WAL is replayed, in seconds. Only reported on standbys.",
+        ))?;
+        descs.extend(replay_delay.desc().into_iter().cloned());
+
+        let status = IntGaugeVec::new(
+            opts(
+                WAL_RECEIVER_SUBSYSTEM,
+                "status",
+                "Current status of the WAL receiver, always 1.",
+            ),
+            &["sender_host", "slot_name", "status"],
+        )?;
+        descs.extend(status.desc().into_iter().cloned());
+
+        let received_lsn = GaugeVec::new(
+            opts(
+                WAL_RECEIVER_SUBSYSTEM,
+                "received_lsn_bytes",
+                "Last WAL location received by the WAL receiver, in bytes. Equals the flushed location before Postgres 13.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(received_lsn.desc().into_iter().cloned());
+
+        let flushed_lsn = GaugeVec::new(
+            opts(
+                WAL_RECEIVER_SUBSYSTEM,
+                "flushed_lsn_bytes",
+                "Last WAL location received and flushed to disk by the WAL receiver, in bytes.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(flushed_lsn.desc().into_iter().cloned());
+
+        let latest_end_time = GaugeVec::new(
+            opts(
+                WAL_RECEIVER_SUBSYSTEM,
+                "latest_end_time_seconds",
+                "Time of the last WAL location reported back to the sender, in unixtime.",
+            ),
+            &label_names,
+        )?;
+        descs.extend(latest_end_time.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(PGStandbyStats::default())),
+            descs,
+            is_standby,
+            replay_delay,
+            status,
+            received_lsn,
+            flushed_lsn,
+            latest_end_time,
+        })
+    }
+}
+
+impl Collector for PGWalReceiverCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(6);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg wal receiver collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        self.status.reset();
+        self.received_lsn.reset();
+        self.flushed_lsn.reset();
+        self.latest_end_time.reset();
+
+        self.is_standby
+            .set(i64::from(data_lock.recovery.is_standby));
+        mfs.extend(self.is_standby.collect());
+
+        if let Some(delay) = data_lock.recovery.replay_delay_seconds {
+            self.replay_delay.set(delay);
+            mfs.extend(self.replay_delay.collect());
+        }
+
+        if let Some(receiver) = &data_lock.wal_receiver {
+            let sender_host = receiver.sender_host.as_deref().unwrap_or_default();
+            let slot_name = receiver.slot_name.as_deref().unwrap_or_default();
+            let labels = [sender_host, slot_name];
+
+            self.status
+                .with_label_values(&[
+                    sender_host,
+                    slot_name,
+                    receiver.status.as_deref().unwrap_or_default(),
+                ])
+                .set(1);
+
+            if let Some(received_lsn) = receiver.received_lsn {
+                self.received_lsn
+                    .with_label_values(&labels)
+                    .set(received_lsn);
+            }
+            if let Some(flushed_lsn) = receiver.flushed_lsn {
+                self.flushed_lsn.with_label_values(&labels).set(flushed_lsn);
+            }
+            if let Some(latest_end_time) = receiver.latest_end_time {
+                self.latest_end_time
+                    .with_label_values(&labels)
+                    .set(latest_end_time);
+            }
+        }
+
+        mfs.extend(self.status.collect());
+        mfs.extend(self.received_lsn.collect());
+        mfs.extend(self.flushed_lsn.collect());
+        mfs.extend(self.latest_end_time.collect());
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGWalReceiverCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn is_supported(&self, cfg: &instance::PGConfig) -> bool {
+        supported(cfg)
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+
+        let recovery_query = if cfg.pg_version < POSTGRES_V10 {
+            RECOVERY_QUERY96
+        } else {
+            RECOVERY_QUERY_LATEST
+        };
+        let recovery = sqlx::query_as::<_, PGRecoveryStats>(recovery_query)
+            .fetch_one(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let wal_receiver_query = if cfg.pg_version < POSTGRES_V11 {
+            WAL_RECEIVER_QUERY10
+        } else if cfg.pg_version < POSTGRES_V13 {
+            WAL_RECEIVER_QUERY12
+        } else {
+            WAL_RECEIVER_QUERY_LATEST
+        };
+        let wal_receiver = sqlx::query_as::<_, PGWalReceiverStats>(wal_receiver_query)
+            .fetch_optional(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg wal receiver collector: can't acquire write lock. {}", e),
+        };
+
+        *data_lock = PGStandbyStats {
+            recovery,
+            wal_receiver,
+        };
+
+        Ok(())
+    }
+}
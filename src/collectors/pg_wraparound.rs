@@ -0,0 +1,337 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use prometheus::IntGaugeVec;
+use prometheus::core::{Collector, Desc, Opts};
+use prometheus::proto;
+use tracing::error;
+
+use crate::collectors::PG;
+use crate::instance;
+
+pub const NAME: &str = "pg_wraparound";
+
+const WRAPAROUND_SUBSYSTEM: &str = "wraparound";
+
+// Transaction and multixact IDs wrap around after 2^31 IDs, Postgres stops assigning new ones shortly before.
+const WRAPAROUND_LIMIT: i64 = i32::MAX as i64;
+
+const DATABASE_AGE_QUERY: &str = "SELECT datname AS database,
+    age(datfrozenxid)::int8 AS xid_age, mxid_age(datminmxid)::int8 AS mxid_age,
+    current_setting('autovacuum_freeze_max_age')::int8 AS freeze_max_age,
+    current_setting('autovacuum_multixact_freeze_max_age')::int8 AS multixact_freeze_max_age
+    FROM pg_database WHERE datname != ALL($1) AND datname != ''";
+
+// Tables, materialized views and TOAST tables with the oldest relfrozenxid or relminmxid,
+// all of them when the top-N is not positive.
+const TABLE_AGE_QUERY: &str = "WITH ages AS (SELECT n.nspname AS schema, c.relname AS table,
+    age(c.relfrozenxid)::int8 AS xid_age, mxid_age(c.relminmxid)::int8 AS mxid_age
+    FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace WHERE c.relkind IN ('r', 'm', 't')),
+    ranked AS (SELECT *, row_number() OVER (ORDER BY xid_age DESC) AS xid_rank,
+    row_number() OVER (ORDER BY mxid_age DESC) AS mxid_rank FROM ages)
+    SELECT current_database() AS database, schema, \"table\", xid_age, mxid_age
+    FROM ranked WHERE $1 <= 0 OR xid_rank <= $1 OR mxid_rank <= $1";
+
+// The oldest xmin of every kind of holder which keeps vacuum from freezing rows. Walsenders
+// are reported as standby feedback instead of backends, the exporter's own backend is skipped.
+const OLDEST_XMIN_QUERY: &str = "(SELECT 'backend' AS holder, a.pid::text AS id,
+    greatest(age(a.backend_xmin), age(a.backend_xid))::int8 AS xmin_age
+    FROM pg_stat_activity a WHERE coalesce(a.backend_xmin, a.backend_xid) IS NOT NULL
+    AND a.pid <> pg_backend_pid()
+    AND NOT EXISTS (SELECT 1 FROM pg_stat_replication r WHERE r.pid = a.pid)
+    ORDER BY 3 DESC LIMIT 1)
+    UNION ALL (SELECT 'prepared_transaction', gid, age(transaction)::int8
+    FROM pg_prepared_xacts ORDER BY 3 DESC LIMIT 1)
+    UNION ALL (SELECT 'replication_slot', slot_name::text, greatest(age(xmin), age(catalog_xmin))::int8
+    FROM pg_replication_slots WHERE coalesce(xmin, catalog_xmin) IS NOT NULL ORDER BY 3 DESC LIMIT 1)
+    UNION ALL (SELECT 'standby_feedback', application_name, age(backend_xmin)::int8
+    FROM pg_stat_replication WHERE backend_xmin IS NOT NULL ORDER BY 3 DESC LIMIT 1)";
+
+#[derive(sqlx::FromRow, Debug, Default)]
+struct DatabaseAge {
+    database: String,
+    xid_age: i64,
+    mxid_age: i64,
+    freeze_max_age: i64,
+    multixact_freeze_max_age: i64,
+}
+
+#[derive(sqlx::FromRow, Debug, Default)]
+struct TableAge {
+    database: String,
+    schema: String,
+    table: String,
+    xid_age: i64,
+    mxid_age: i64,
+}
+
+#[derive(sqlx::FromRow, Debug, Default)]
+struct XminHolder {
+    holder: String,
+    id: Option<String>,
+    xmin_age: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct PGWraparoundStats {
+    databases: Vec<DatabaseAge>,
+    tables: Vec<TableAge>,
+    holders: Vec<XminHolder>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PGWraparoundCollector {
+    dbi: Arc<instance::PostgresDB>,
+    data: Arc<RwLock<PGWraparoundStats>>,
+    descs: Vec<Desc>,
+    database_xid_age: IntGaugeVec,
+    database_mxid_age: IntGaugeVec,
+    database_xids_until_autovacuum: IntGaugeVec,
+    database_xids_until_wraparound: IntGaugeVec,
+    database_mxids_until_autovacuum: IntGaugeVec,
+    database_mxids_until_wraparound: IntGaugeVec,
+    table_xid_age: IntGaugeVec,
+    table_mxid_age: IntGaugeVec,
+    oldest_xmin_age: IntGaugeVec,
+}
+
+pub fn new(dbi: Arc<instance::PostgresDB>) -> Option<PGWraparoundCollector> {
+    match PGWraparoundCollector::new(dbi) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            error!("error when create pg wraparound collector: {}", e);
+            None
+        }
+    }
+}
+
+impl PGWraparoundCollector {
+    fn new(dbi: Arc<instance::PostgresDB>) -> anyhow::Result<Self> {
+        let mut descs = Vec::new();
+
+        let gauge = |name: &str, help: &str, labels: &[&str]| {
+            IntGaugeVec::new(
+                Opts::new(name, help)
+                    .namespace(super::NAMESPACE)
+                    .subsystem(WRAPAROUND_SUBSYSTEM)
+                    .const_labels(dbi.labels.clone()),
+                labels,
+            )
+        };
+
+        let database_xid_age = gauge(
+            "database_xid_age",
+            "Age of the oldest unfrozen transaction ID of the database, age(datfrozenxid).",
+            &["database"],
+        )?;
+        descs.extend(database_xid_age.desc().into_iter().cloned());
+
+        let database_mxid_age = gauge(
+            "database_mxid_age",
+            "Age of the oldest unfrozen multixact ID of the database, mxid_age(datminmxid).",
+            &["database"],
+        )?;
+        descs.extend(database_mxid_age.desc().into_iter().cloned());
+
+        let database_xids_until_autovacuum = gauge(
+            "database_xids_until_autovacuum",
+            "Transaction IDs left before autovacuum_freeze_max_age forces an anti-wraparound autovacuum, negative when overdue.",
+            &["database"],
+        )?;
+        descs.extend(database_xids_until_autovacuum.desc().into_iter().cloned());
+
+        let database_xids_until_wraparound = gauge(
+            "database_xids_until_wraparound",
+            "Transaction IDs left before wraparound.",
+            &["database"],
+        )?;
+        descs.extend(database_xids_until_wraparound.desc().into_iter().cloned());
+
+        let database_mxids_until_autovacuum = gauge(
+            "database_mxids_until_autovacuum",
+            "Multixact IDs left before autovacuum_multixact_freeze_max_age forces an anti-wraparound autovacuum, negative when overdue.",
+            &["database"],
+        )?;
+        descs.extend(database_mxids_until_autovacuum.desc().into_iter().cloned());
+
+        let database_mxids_until_wraparound = gauge(
+            "database_mxids_until_wraparound",
+            "Multixact IDs left before wraparound.",
+            &["database"],
+        )?;
+        descs.extend(database_mxids_until_wraparound.desc().into_iter().cloned());
+
+        let table_xid_age = gauge(
+            "table_xid_age",
+            "Age of the oldest unfrozen transaction ID of the table, age(relfrozenxid).",
+            &["database", "schema", "table"],
+        )?;
+        descs.extend(table_xid_age.desc().into_iter().cloned());
+
+        let table_mxid_age = gauge(
+            "table_mxid_age",
+            "Age of the oldest unfrozen multixact ID of the table, mxid_age(relminmxid).",
+            &["database", "schema", "table"],
+        )?;
+        descs.extend(table_mxid_age.desc().into_iter().cloned());
+
+        let oldest_xmin_age = gauge(
+            "oldest_xmin_age",
+            "Age of the oldest xmin held by a backend, a prepared transaction, a replication slot or a standby feedback, it limits freezing.",
+            &["holder", "id"],
+        )?;
+        descs.extend(oldest_xmin_age.desc().into_iter().cloned());
+
+        Ok(Self {
+            dbi,
+            data: Arc::new(RwLock::new(PGWraparoundStats::default())),
+            descs,
+            database_xid_age,
+            database_mxid_age,
+            database_xids_until_autovacuum,
+            database_xids_until_wraparound,
+            database_mxids_until_autovacuum,
+            database_mxids_until_wraparound,
+            table_xid_age,
+            table_mxid_age,
+            oldest_xmin_age,
+        })
+    }
+}
+
+impl Collector for PGWraparoundCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        // collect MetricFamilies.
+        let mut mfs = Vec::with_capacity(9);
+
+        let data_lock = match self.data.read() {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("pg wraparound collect: can't acquire read lock: {}", e);
+                // return empty mfs
+                return mfs;
+            }
+        };
+
+        let gauges = [
+            &self.database_xid_age,
+            &self.database_mxid_age,
+            &self.database_xids_until_autovacuum,
+            &self.database_xids_until_wraparound,
+            &self.database_mxids_until_autovacuum,
+            &self.database_mxids_until_wraparound,
+            &self.table_xid_age,
+            &self.table_mxid_age,
+            &self.oldest_xmin_age,
+        ];
+        for gauge in gauges {
+            gauge.reset();
+        }
+
+        for row in &data_lock.databases {
+            let labels = [row.database.as_str()];
+
+            self.database_xid_age
+                .with_label_values(&labels)
+                .set(row.xid_age);
+            self.database_mxid_age
+                .with_label_values(&labels)
+                .set(row.mxid_age);
+            self.database_xids_until_autovacuum
+                .with_label_values(&labels)
+                .set(row.freeze_max_age - row.xid_age);
+            self.database_xids_until_wraparound
+                .with_label_values(&labels)
+                .set(WRAPAROUND_LIMIT - row.xid_age);
+            self.database_mxids_until_autovacuum
+                .with_label_values(&labels)
+                .set(row.multixact_freeze_max_age - row.mxid_age);
+            self.database_mxids_until_wraparound
+                .with_label_values(&labels)
+                .set(WRAPAROUND_LIMIT - row.mxid_age);
+        }
+
+        for row in &data_lock.tables {
+            let labels = [
+                row.database.as_str(),
+                row.schema.as_str(),
+                row.table.as_str(),
+            ];
+
+            self.table_xid_age
+                .with_label_values(&labels)
+                .set(row.xid_age);
+            self.table_mxid_age
+                .with_label_values(&labels)
+                .set(row.mxid_age);
+        }
+
+        for row in &data_lock.holders {
+            if let Some(xmin_age) = row.xmin_age {
+                self.oldest_xmin_age
+                    .with_label_values(&[
+                        row.holder.as_str(),
+                        row.id.as_deref().unwrap_or_default(),
+                    ])
+                    .set(xmin_age);
+            }
+        }
+
+        for gauge in gauges {
+            mfs.extend(gauge.collect());
+        }
+
+        mfs
+    }
+}
+
+#[async_trait]
+impl PG for PGWraparoundCollector {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    async fn update(&self) -> Result<(), anyhow::Error> {
+        let cfg = self.dbi.ensure_ready().await?;
+
+        let databases = sqlx::query_as::<_, DatabaseAge>(DATABASE_AGE_QUERY)
+            .bind(&self.dbi.excluded_db_names)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let holders = sqlx::query_as::<_, XminHolder>(OLDEST_XMIN_QUERY)
+            .fetch_all(&mut *self.dbi.acquire().await?)
+            .await?;
+
+        let tables = self
+            .dbi
+            .query_databases("pg wraparound collector", |pool| async move {
+                let rows = sqlx::query_as::<_, TableAge>(TABLE_AGE_QUERY)
+                    .bind(cfg.pg_collect_top_wraparound)
+                    .fetch_all(&mut *pool.acquire().await?)
+                    .await?;
+                Ok(rows)
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut data_lock = match self.data.write() {
+            Ok(data_lock) => data_lock,
+            Err(e) => bail!("pg wraparound collector: can't acquire write lock. {}", e),
+        };
+
+        data_lock.databases = databases;
+        data_lock.tables = tables;
+        data_lock.holders = holders;
+
+        Ok(())
+    }
+}
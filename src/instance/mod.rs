@@ -1,12 +1,52 @@
 use anyhow::bail;
-use sqlx::{Pool, Postgres, postgres::PgPoolOptions};
+use sqlx::{
+    Pool, Postgres,
+    postgres::{PgConnectOptions, PgPoolOptions, PgSslMode},
+};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 use crate::collectors;
 
+mod credentials;
+mod pool;
+
+pub use credentials::{read_password_env, read_password_file, redact_dsn};
+pub use pool::{PoolUsage, TrackedConnection, TrackedPool};
+
+// Upper bound of max connections of the pools opened to the databases discovered with `discover_databases`.
+const DATABASE_POOL_MAX_CONNECTIONS: u32 = 2;
+
+// Tables with the oldest unfrozen IDs reported per database when `collect_top_wraparound` is not set.
+const DEFAULT_TOP_WRAPAROUND: i64 = 10;
+
+// Tables and indexes with the most bloat reported per database when `collect_top_bloat` is not set.
+const DEFAULT_TOP_BLOAT: i64 = 10;
+
+const DATABASES_QUERY: &str = "SELECT datname FROM pg_database \
+     WHERE datallowconn AND NOT datistemplate AND datname != ALL($1) AND datname != ''";
+
+const SSL_STATE_QUERY: &str = "SELECT ssl, COALESCE(version, '') AS version, \
+     COALESCE(cipher, '') AS cipher FROM pg_stat_ssl WHERE pid = pg_backend_pid()";
+
+// Identifies the running server: a new start time or system identifier means a restart, an upgrade
+// or a failover to another server, a changed recovery state means a promotion.
+const SERVER_IDENTITY_QUERY: &str = "SELECT pg_postmaster_start_time()::text AS start_time, \
+     pg_is_in_recovery() AS in_recovery, \
+     (SELECT system_identifier::text FROM pg_control_system()) AS system_identifier";
+
+// pg_control_system() is available since Postgres 9.6.
+const SERVER_IDENTITY_QUERY_V95: &str = "SELECT pg_postmaster_start_time()::text AS start_time, \
+     pg_is_in_recovery() AS in_recovery, NULL::text AS system_identifier";
+
+// Applies all session settings in one round trip, values are passed as parameters and never interpolated.
+const SESSION_SETTINGS_QUERY: &str = "SELECT set_config(name, value, false) \
+     FROM unnest($1::text[], $2::text[]) AS s(name, value)";
+
 #[derive(Debug, Clone)]
 pub struct PGConfig {
     pub pg_version: i64,
@@ -15,6 +55,10 @@ pub struct PGConfig {
     pub pg_collect_topidx: i64,
     pub pg_collect_topq: i64,
     pub pg_collect_top_table: i64,
+    pub pg_collect_top_wraparound: i64,
+    // pg_collect_top_bloat limits the tables and indexes reported by pg_bloat per database, 0 reports all.
+    pub pg_collect_top_bloat: i64,
+    pub pg_collect_top_function: i64,
     // NoTrackMode controls collector to gather and send sensitive information, such as queries texts.
     pub notrack: bool,
     // pg_stat_statements defines is pg_stat_statements available in shared_preload_libraries and available for queries.
@@ -25,47 +69,442 @@ pub struct PGConfig {
 
 #[derive(Debug, Clone)]
 pub struct PostgresDB {
+    // Name of the instance from the `instances` config section.
+    pub name: String,
     pub db: Pool<Postgres>,
     pub excluded_db_names: Vec<String>,
     pub labels: HashMap<String, String>,
     cfg: Arc<RwLock<Option<PGConfig>>>,
     source_cfg: Config,
+    // Lazily created pools of the databases found on the server, used with `discover_databases`.
+    db_pools: Arc<RwLock<HashMap<String, TrackedPool>>>,
+    // Acquires of collectors from `db`.
+    usage: Arc<PoolUsage>,
+    // Passwords last read from `password_file` or `.pgpass` per database, new connections use them.
+    passwords: Arc<RwLock<HashMap<String, String>>>,
+    // Identity of the server the cached config was fetched from.
+    identity: Arc<RwLock<Option<ServerIdentity>>>,
+}
+
+/// Identity of the server behind the DSN, used to notice restarts, upgrades and failovers.
+#[derive(Debug, Default, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ServerIdentity {
+    pub start_time: String,
+    pub in_recovery: bool,
+    pub system_identifier: Option<String>,
 }
 
 // TODO: make fields Optional
 #[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
 pub struct Config {
+    #[serde(default)]
     pub dsn: String,
+    // Connection parameters, they override the ones from the DSN.
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub dbname: Option<String>,
+    // PasswordFile is re-read whenever its content changes, so that rotated secrets are picked up.
+    pub password_file: Option<PathBuf>,
+    // PasswordEnv names the environment variable holding the password.
+    pub password_env: Option<String>,
+    // Passfile is the .pgpass file looked up when no password is set, `PGPASSFILE` or `~/.pgpass` by default.
+    pub passfile: Option<PathBuf>,
     pub exclude_db_names: Option<Vec<String>>,
     pub const_labels: HashMap<String, String>,
     pub collect_top_query: Option<i64>,
     pub collect_top_index: Option<i64>,
     pub collect_top_table: Option<i64>,
+    // CollectTopWraparound limits the tables with the oldest unfrozen IDs per database, 0 reports all tables.
+    pub collect_top_wraparound: Option<i64>,
+    // CollectTopBloat limits the tables and indexes with the most estimated bloat per database, 0 reports all
+    // of them with estimates only, pgstattuple_approx measures the top-N tables.
+    pub collect_top_bloat: Option<i64>,
+    pub collect_top_function: Option<i64>,
     pub no_track_mode: Option<bool>,
+    pub custom_queries: Option<Vec<collectors::pg_custom::CustomQuery>>,
+    // SettingsInfo lists the settings exported as info metrics with their value as a label, for string and enum settings.
+    pub settings_info: Option<Vec<String>>,
+    // DiscoverDatabases runs per-database collectors (tables, indexes) against every database of the instance.
+    pub discover_databases: Option<bool>,
+    // Collectors selects the collectors registered for the instance, all collectors are registered by default.
+    pub collectors: Option<collectors::CollectorsConfig>,
+    // Session settings applied on every connection opened by the exporter.
+    pub session: Option<SessionSettings>,
+    // Pool configures the connection pool of the instance.
+    pub pool: Option<PoolSettings>,
+    // TLS options of the connections, they override the ones from the DSN.
+    pub tls: Option<TlsConfig>,
+}
+
+impl Config {
+    /// Returns the options parsed from the DSN with the connection parameters, the password
+    /// and the TLS options applied. Without a DSN the `PG*` environment variables are used.
+    pub fn connect_options(&self) -> anyhow::Result<PgConnectOptions> {
+        let mut opts = if self.dsn.is_empty() {
+            PgConnectOptions::new_without_pgpass()
+        } else {
+            PgConnectOptions::from_str(&self.dsn)?
+        };
+
+        if let Some(host) = &self.host {
+            opts = opts.host(host);
+        }
+
+        if let Some(port) = self.port {
+            opts = opts.port(port);
+        }
+
+        if let Some(user) = &self.user {
+            opts = opts.username(user);
+        }
+
+        if let Some(dbname) = &self.dbname {
+            opts = opts.database(dbname);
+        }
+
+        if let Some(password) = self.password(&opts)? {
+            opts = opts.password(&password);
+        }
+
+        match &self.tls {
+            Some(tls) => tls.apply(opts),
+            None => Ok(opts),
+        }
+    }
+
+    /// Returns the password from `password_file` or `password_env`. Otherwise, unless the DSN or
+    /// `PGPASSWORD` sets one, the password of the matching `.pgpass` entry.
+    fn password(&self, opts: &PgConnectOptions) -> anyhow::Result<Option<String>> {
+        if let Some(path) = &self.password_file {
+            return credentials::read_password_file(path).map(Some);
+        }
+
+        if let Some(name) = &self.password_env {
+            return credentials::read_password_env(name).map(Some);
+        }
+
+        if credentials::dsn_has_password(&self.dsn) || std::env::var_os("PGPASSWORD").is_some() {
+            return Ok(None);
+        }
+
+        let passfile = self.passfile.clone().or_else(credentials::default_passfile);
+        Ok(passfile.and_then(|path| {
+            credentials::pgpass_password(
+                &path,
+                opts.get_host(),
+                opts.get_port(),
+                opts.get_database().unwrap_or(opts.get_username()),
+                opts.get_username(),
+            )
+        }))
+    }
+
+    /// Checks that at most one password source is set and that the password file exists.
+    pub fn validate_credentials(&self) -> anyhow::Result<()> {
+        if self.password_file.is_some() && self.password_env.is_some() {
+            bail!("password_file and password_env are mutually exclusive");
+        }
+
+        if let Some(path) = &self.password_file
+            && !path.is_file()
+        {
+            bail!("password_file: file {} not found", path.display());
+        }
+
+        Ok(())
+    }
+}
+
+/// TLS options of PostgreSQL connections, named after the libpq parameters.
+/// `verify-full` checks the certificate against the DSN host, sqlx can't expect another server name.
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct TlsConfig {
+    // disable, allow, prefer, require, verify-ca or verify-full.
+    pub sslmode: Option<String>,
+    // CA certificates the server certificate is verified with.
+    pub sslrootcert: Option<PathBuf>,
+    // Client certificate and its key for certificate authentication.
+    pub sslcert: Option<PathBuf>,
+    pub sslkey: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn apply(&self, mut opts: PgConnectOptions) -> anyhow::Result<PgConnectOptions> {
+        if let Some(mode) = &self.sslmode {
+            opts = opts.ssl_mode(PgSslMode::from_str(mode)?);
+        }
+
+        if let Some(root_cert) = &self.sslrootcert {
+            opts = opts.ssl_root_cert(root_cert);
+        }
+
+        if let Some(cert) = &self.sslcert {
+            opts = opts.ssl_client_cert(cert);
+        }
+
+        if let Some(key) = &self.sslkey {
+            opts = opts.ssl_client_key(key);
+        }
+
+        Ok(opts)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(mode) = &self.sslmode {
+            PgSslMode::from_str(mode).map_err(|e| anyhow::anyhow!("sslmode: {e}"))?;
+        }
+
+        if self.sslcert.is_some() != self.sslkey.is_some() {
+            bail!("sslcert and sslkey should be set together");
+        }
+
+        for (key, path) in [
+            ("sslrootcert", &self.sslrootcert),
+            ("sslcert", &self.sslcert),
+            ("sslkey", &self.sslkey),
+        ] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                bail!("{key}: file {} not found", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// SSL state of an exporter connection as reported by `pg_stat_ssl`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct SslState {
+    pub ssl: bool,
+    pub version: String,
+    pub cipher: String,
+}
+
+/// Settings applied with `set_config()` on every exporter connection. Values use the PostgreSQL
+/// syntax (e.g. `5s`, `64MB`), `0` disables a timeout. Unset fields fall back to safe defaults,
+/// except `work_mem` which keeps the server default.
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct SessionSettings {
+    pub statement_timeout: Option<String>,
+    pub lock_timeout: Option<String>,
+    pub application_name: Option<String>,
+    pub idle_in_transaction_session_timeout: Option<String>,
+    pub work_mem: Option<String>,
+}
+
+impl SessionSettings {
+    const DEFAULT_STATEMENT_TIMEOUT: &str = "10s";
+    const DEFAULT_LOCK_TIMEOUT: &str = "1s";
+    const DEFAULT_APPLICATION_NAME: &str = "pg_exporter";
+    const DEFAULT_IDLE_IN_TRANSACTION_SESSION_TIMEOUT: &str = "60s";
+
+    /// Returns names and values of the parameters to set, with defaults applied.
+    pub fn parameters(&self) -> Vec<(&'static str, String)> {
+        let with_default =
+            |value: &Option<String>, default: &str| value.as_deref().unwrap_or(default).to_string();
+
+        let mut params = vec![
+            (
+                "statement_timeout",
+                with_default(&self.statement_timeout, Self::DEFAULT_STATEMENT_TIMEOUT),
+            ),
+            (
+                "lock_timeout",
+                with_default(&self.lock_timeout, Self::DEFAULT_LOCK_TIMEOUT),
+            ),
+            (
+                "application_name",
+                with_default(&self.application_name, Self::DEFAULT_APPLICATION_NAME),
+            ),
+            (
+                "idle_in_transaction_session_timeout",
+                with_default(
+                    &self.idle_in_transaction_session_timeout,
+                    Self::DEFAULT_IDLE_IN_TRANSACTION_SESSION_TIMEOUT,
+                ),
+            ),
+        ];
+
+        if let Some(work_mem) = &self.work_mem {
+            params.push(("work_mem", work_mem.clone()));
+        }
+
+        params
+    }
+
+    /// Checks the values like the server would, so that a typo fails at startup instead of
+    /// every new connection.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("statement_timeout", &self.statement_timeout),
+            ("lock_timeout", &self.lock_timeout),
+            (
+                "idle_in_transaction_session_timeout",
+                &self.idle_in_transaction_session_timeout,
+            ),
+        ] {
+            if let Some(value) = value
+                && !matches!(
+                    split_unit(value),
+                    Some((_, "" | "us" | "ms" | "s" | "min" | "h" | "d"))
+                )
+            {
+                bail!(
+                    "{name}: invalid value {value}, expected a non-negative number with an optional unit us, ms, s, min, h or d"
+                );
+            }
+        }
+
+        if let Some(work_mem) = &self.work_mem {
+            let kilobytes = match split_unit(work_mem) {
+                Some((n, "B")) => n / 1024.0,
+                Some((n, "" | "kB")) => n,
+                Some((n, "MB")) => n * 1024.0,
+                Some((n, "GB")) => n * 1024.0 * 1024.0,
+                Some((n, "TB")) => n * 1024.0 * 1024.0 * 1024.0,
+                _ => bail!(
+                    "work_mem: invalid value {work_mem}, expected a number with an optional unit B, kB, MB, GB or TB"
+                ),
+            };
+            if kilobytes < 64.0 {
+                bail!("work_mem: {work_mem} is less than the minimum of 64kB");
+            }
+        }
+
+        if let Some(name) = &self.application_name
+            && !name.is_ascii()
+        {
+            bail!("application_name: only ASCII characters are kept by the server");
+        }
+
+        Ok(())
+    }
+}
+
+// Splits a setting value such as `30s` or `64MB` into its number and unit.
+fn split_unit(value: &str) -> Option<(f64, &str)> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let number = value[..end].parse::<f64>().ok()?;
+
+    Some((number, value[end..].trim_start()))
+}
+
+/// Connection pool parameters of the instance. Unset fields use the defaults below.
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct PoolSettings {
+    pub min_connections: Option<u32>,
+    pub max_connections: Option<u32>,
+    pub acquire_timeout_seconds: Option<u64>,
+    pub idle_timeout_seconds: Option<u64>,
+    pub max_lifetime_seconds: Option<u64>,
+}
+
+impl PoolSettings {
+    const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+    const DEFAULT_ACQUIRE_TIMEOUT_SECONDS: u64 = 5;
+    const DEFAULT_IDLE_TIMEOUT_SECONDS: u64 = 300;
+    const DEFAULT_MAX_LIFETIME_SECONDS: u64 = 1800;
+
+    pub fn min_connections(&self) -> u32 {
+        self.min_connections.unwrap_or_default()
+    }
+
+    pub fn max_connections(&self) -> u32 {
+        self.max_connections
+            .unwrap_or(Self::DEFAULT_MAX_CONNECTIONS)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_connections() == 0 {
+            bail!("max_connections should be greater than 0");
+        }
+
+        if self.min_connections() > self.max_connections() {
+            bail!(
+                "min_connections ({}) should not exceed max_connections ({})",
+                self.min_connections(),
+                self.max_connections()
+            );
+        }
+
+        if self.acquire_timeout_seconds == Some(0) {
+            bail!("acquire_timeout_seconds should be greater than 0");
+        }
+
+        Ok(())
+    }
 }
 
-pub async fn new(instance_cfg: &Config) -> anyhow::Result<PostgresDB> {
-    let pool = PgPoolOptions::new()
-        .max_connections(10)
+fn pool_options(cfg: &Config) -> PgPoolOptions {
+    let pool = cfg.pool.clone().unwrap_or_default();
+    let (names, values): (Vec<&'static str>, Vec<String>) = cfg
+        .session
+        .clone()
+        .unwrap_or_default()
+        .parameters()
+        .into_iter()
+        .unzip();
+    let params = Arc::new((names, values));
+
+    // zero idle timeout and lifetime keep connections forever.
+    let non_zero = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+
+    PgPoolOptions::new()
+        .min_connections(pool.min_connections())
+        .max_connections(pool.max_connections())
         .test_before_acquire(true)
-        .acquire_timeout(Duration::from_secs(5))
-        .idle_timeout(Duration::from_secs(300))
-        .max_lifetime(Duration::from_secs(1800))
-        .connect_lazy(&instance_cfg.dsn)?;
-
-    let pgi = PostgresDB {
-        db: pool,
-        excluded_db_names: instance_cfg.exclude_db_names.clone().unwrap_or_default(),
-        labels: instance_cfg.const_labels.clone(),
-        cfg: Arc::new(RwLock::new(None)),
-        source_cfg: instance_cfg.clone(),
-    };
+        .acquire_timeout(Duration::from_secs(
+            pool.acquire_timeout_seconds
+                .unwrap_or(PoolSettings::DEFAULT_ACQUIRE_TIMEOUT_SECONDS),
+        ))
+        .idle_timeout(non_zero(
+            pool.idle_timeout_seconds
+                .unwrap_or(PoolSettings::DEFAULT_IDLE_TIMEOUT_SECONDS),
+        ))
+        .max_lifetime(non_zero(
+            pool.max_lifetime_seconds
+                .unwrap_or(PoolSettings::DEFAULT_MAX_LIFETIME_SECONDS),
+        ))
+        .after_connect(move |conn, _meta| {
+            let params = Arc::clone(&params);
+            Box::pin(async move {
+                sqlx::query(SESSION_SETTINGS_QUERY)
+                    .bind(&params.0)
+                    .bind(&params.1)
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            })
+        })
+}
+
+pub async fn new(name: &str, instance_cfg: &Config) -> anyhow::Result<PostgresDB> {
+    let connect_options = instance_cfg.connect_options()?;
+    new_with_options(name, instance_cfg, connect_options).await
+}
+
+/// Like [`new`] but connects with the given options instead of the ones parsed from the DSN.
+pub async fn new_with_options(
+    name: &str,
+    instance_cfg: &Config,
+    connect_options: PgConnectOptions,
+) -> anyhow::Result<PostgresDB> {
+    let pgi = PostgresDB::lazy(name, instance_cfg, connect_options);
+
+    if let Err(e) = pgi.refresh_password() {
+        warn!("pg_exporter: instance {name}: {e}");
+    }
 
     // Eagerly attempt to initialize config, but do not fail if PG is unavailable.
     if let Err(e) = pgi.init_cfg().await {
         warn!(
-            "pg_exporter: instance {}: unable to connect at startup, will retry on first scrape: {e}",
-            instance_cfg.dsn
+            "pg_exporter: instance {name}: unable to connect at startup, will retry on first scrape: {e}"
         );
     }
 
@@ -73,6 +512,22 @@ pub async fn new(instance_cfg: &Config) -> anyhow::Result<PostgresDB> {
 }
 
 impl PostgresDB {
+    /// Creates the instance without connecting, the first query opens the connection.
+    pub fn lazy(name: &str, instance_cfg: &Config, connect_options: PgConnectOptions) -> Self {
+        PostgresDB {
+            name: name.to_string(),
+            db: pool_options(instance_cfg).connect_lazy_with(connect_options),
+            excluded_db_names: instance_cfg.exclude_db_names.clone().unwrap_or_default(),
+            labels: instance_cfg.const_labels.clone(),
+            cfg: Arc::new(RwLock::new(None)),
+            source_cfg: instance_cfg.clone(),
+            db_pools: Arc::new(RwLock::new(HashMap::new())),
+            usage: Arc::new(PoolUsage::default()),
+            passwords: Arc::new(RwLock::new(HashMap::new())),
+            identity: Arc::new(RwLock::new(None)),
+        }
+    }
+
     /// Returns the current PGConfig without blocking if it has been initialized.
     /// Returns `None` when the database has not been reached yet.
     pub fn current_cfg(&self) -> Option<PGConfig> {
@@ -101,6 +556,266 @@ impl PostgresDB {
             .ok_or_else(|| anyhow::anyhow!("pg_exporter: cfg not initialized after init_cfg"))
     }
 
+    /// Checks that the instance accepts connections and answers queries, and refreshes the
+    /// cached config when the server was restarted or replaced by another one.
+    /// Returns the SSL state of the connection the check ran on.
+    pub async fn check_health(&self) -> anyhow::Result<SslState> {
+        self.refresh_password()?;
+        let cfg = self.ensure_ready().await?;
+        self.check_identity(&cfg).await?;
+
+        let state = sqlx::query_as::<_, SslState>(SSL_STATE_QUERY)
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(state.unwrap_or_default())
+    }
+
+    /// Compares the identity of the server with the one the config was fetched from and fetches
+    /// the config again when it changed.
+    async fn check_identity(&self, cfg: &PGConfig) -> anyhow::Result<()> {
+        let query = if cfg.pg_version >= collectors::POSTGRES_V96 {
+            SERVER_IDENTITY_QUERY
+        } else {
+            SERVER_IDENTITY_QUERY_V95
+        };
+        let identity = sqlx::query_as::<_, ServerIdentity>(query)
+            .fetch_one(&self.db)
+            .await?;
+
+        let previous = self
+            .identity
+            .read()
+            .map_err(|e| anyhow::anyhow!("identity lock poisoned: {e}"))?
+            .clone();
+
+        match previous {
+            Some(previous) if previous != identity => {
+                info!(
+                    "instance {}: server changed (started at {}, in recovery: {}), reload its configuration",
+                    self.name, identity.start_time, identity.in_recovery
+                );
+                self.init_cfg().await?;
+
+                if let Some(new_cfg) = self.current_cfg()
+                    && new_cfg.pg_version != cfg.pg_version
+                {
+                    info!(
+                        "instance {}: Postgres version changed from {} to {}",
+                        self.name, cfg.pg_version, new_cfg.pg_version
+                    );
+                }
+            }
+            Some(_) => return Ok(()),
+            None => {}
+        }
+
+        let mut lock = self
+            .identity
+            .write()
+            .map_err(|e| anyhow::anyhow!("identity lock poisoned: {e}"))?;
+        *lock = Some(identity);
+
+        Ok(())
+    }
+
+    /// Re-reads `password_file` or the matching `.pgpass` entry of every pool and, when the
+    /// password has changed, makes the pool open new connections with it. Established connections
+    /// are kept.
+    fn refresh_password(&self) -> anyhow::Result<()> {
+        let pools = self.pools();
+        let mut current = self
+            .passwords
+            .write()
+            .map_err(|e| anyhow::anyhow!("password lock poisoned: {e}"))?;
+
+        for (datname, pool) in &pools {
+            // the password of the DSN or PGPASSWORD is used as is.
+            let Some(password) = self.source_cfg.password(&pool.connect_options())? else {
+                continue;
+            };
+
+            match current.get(datname) {
+                Some(previous) if *previous == password => continue,
+                Some(_) => info!(
+                    "instance {}: password of database {datname} changed, reconnect with the new one",
+                    self.name
+                ),
+                None => {}
+            }
+
+            let options = (*pool.connect_options()).clone().password(&password);
+            pool.set_connect_options(options);
+            current.insert(datname.clone(), password);
+        }
+
+        current.retain(|datname, _| pools.iter().any(|(name, _)| name == datname));
+
+        Ok(())
+    }
+
+    /// Returns the user-defined queries configured for the instance.
+    pub fn custom_queries(&self) -> &[collectors::pg_custom::CustomQuery] {
+        self.source_cfg
+            .custom_queries
+            .as_deref()
+            .unwrap_or_default()
+    }
+
+    /// Returns the names of the settings exported as info metrics.
+    pub fn settings_info(&self) -> &[String] {
+        self.source_cfg.settings_info.as_deref().unwrap_or_default()
+    }
+
+    /// Reports whether the collector with the given name should be registered for the instance.
+    pub fn collector_enabled(&self, name: &str) -> bool {
+        self.source_cfg
+            .collectors
+            .as_ref()
+            .is_none_or(|c| c.is_enabled(name))
+    }
+
+    /// Returns the pools per-database collectors should run against.
+    /// Without `discover_databases` only the pool of the database from the DSN is returned.
+    /// Otherwise every connectable database not listed in `exclude_db_names` is returned and pools
+    /// of databases which no longer exist are closed.
+    pub async fn database_pools(&self) -> anyhow::Result<Vec<TrackedPool>> {
+        if !self.source_cfg.discover_databases.unwrap_or_default() {
+            return Ok(vec![self.tracked_pool()]);
+        }
+
+        let datnames = sqlx::query_scalar::<_, String>(DATABASES_QUERY)
+            .bind(&self.excluded_db_names)
+            .fetch_all(&self.db)
+            .await?;
+
+        let connect_options = self.db.connect_options();
+        let main_db = connect_options
+            .get_database()
+            .unwrap_or_default()
+            .to_string();
+
+        let max_connections = self.db.options().get_max_connections();
+
+        let (pools, removed) = {
+            let mut pools_lock = self
+                .db_pools
+                .write()
+                .map_err(|e| anyhow::anyhow!("db pools lock poisoned: {e}"))?;
+
+            let removed: Vec<(String, TrackedPool)> = pools_lock
+                .extract_if(|name, _| !datnames.contains(name))
+                .collect();
+
+            let mut pools = Vec::with_capacity(datnames.len());
+            for datname in &datnames {
+                if *datname == main_db {
+                    pools.push(self.tracked_pool());
+                    continue;
+                }
+
+                let pool = pools_lock.entry(datname.clone()).or_insert_with(|| {
+                    info!("open connection pool for database {datname}");
+                    let mut options = (*connect_options).clone().database(datname);
+                    // .pgpass entries may differ per database, a failed lookup keeps the
+                    // password of the DSN database until the next refresh reports it.
+                    if let Ok(Some(password)) = self.source_cfg.password(&options) {
+                        options = options.password(&password);
+                    }
+                    let pool = pool_options(&self.source_cfg)
+                        .min_connections(0)
+                        .max_connections(max_connections.min(DATABASE_POOL_MAX_CONNECTIONS))
+                        .connect_lazy_with(options);
+                    TrackedPool::new(pool, Arc::new(PoolUsage::default()))
+                });
+                pools.push(pool.clone());
+            }
+
+            (pools, removed)
+        };
+
+        for (datname, pool) in removed {
+            info!("database {datname} is gone, close its connection pool");
+            pool.close().await;
+        }
+
+        Ok(pools)
+    }
+
+    /// Runs `query` against every pool returned by `database_pools` and returns the results.
+    /// A failing database is logged and skipped, an error is returned only when no database
+    /// could be queried at all.
+    pub async fn query_databases<T, F, Fut>(
+        &self,
+        collector: &str,
+        query: F,
+    ) -> anyhow::Result<Vec<T>>
+    where
+        F: Fn(TrackedPool) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let pools = self.database_pools().await?;
+
+        let mut results = Vec::with_capacity(pools.len());
+        let mut last_err = None;
+        for pool in pools {
+            let datname = pool
+                .connect_options()
+                .get_database()
+                .unwrap_or_default()
+                .to_string();
+            match query(pool).await {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    error!("{collector}: database {datname}: {e}");
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        match last_err {
+            Some(e) if results.is_empty() => Err(e),
+            _ => Ok(results),
+        }
+    }
+
+    /// Acquires a connection of the pool of the database from the DSN for a collector query.
+    pub async fn acquire(&self) -> Result<TrackedConnection, sqlx::Error> {
+        self.tracked_pool().acquire().await
+    }
+
+    fn tracked_pool(&self) -> TrackedPool {
+        TrackedPool::new(self.db.clone(), self.usage.clone())
+    }
+
+    /// Returns all pools opened by the instance together with their database names.
+    pub fn pools(&self) -> Vec<(String, TrackedPool)> {
+        let main_db = self
+            .db
+            .connect_options()
+            .get_database()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut pools = vec![(main_db, self.tracked_pool())];
+        if let Ok(db_pools) = self.db_pools.read() {
+            pools.extend(
+                db_pools
+                    .iter()
+                    .map(|(name, pool)| (name.clone(), pool.clone())),
+            );
+        }
+
+        pools
+    }
+
+    /// Closes all pools of the instance.
+    pub async fn close(&self) {
+        for (_, pool) in self.pools() {
+            pool.close().await;
+        }
+    }
+
     /// Clears the cached config so that the next `ensure_ready()` call re-fetches it from the
     /// database. Intended for testing lazy-reconnect behavior.
     #[allow(dead_code)]
@@ -182,8 +897,53 @@ async fn fetch_cfg(pool: &Pool<Postgres>, instance_cfg: &Config) -> anyhow::Resu
         pg_collect_topidx: instance_cfg.collect_top_index.unwrap_or_default(),
         pg_collect_topq: instance_cfg.collect_top_query.unwrap_or_default(),
         pg_collect_top_table: instance_cfg.collect_top_table.unwrap_or_default(),
+        pg_collect_top_wraparound: instance_cfg
+            .collect_top_wraparound
+            .unwrap_or(DEFAULT_TOP_WRAPAROUND),
+        pg_collect_top_bloat: instance_cfg.collect_top_bloat.unwrap_or(DEFAULT_TOP_BLOAT),
+        pg_collect_top_function: instance_cfg.collect_top_function.unwrap_or_default(),
         notrack: instance_cfg.no_track_mode.unwrap_or_default(),
         pg_stat_statements: exist,
         pg_stat_statements_schema: scheme,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pgpass_password_refreshed() {
+        let dir = std::env::temp_dir().join(format!("pg_exporter_refresh_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp dir should be created");
+        let passfile = dir.join("pgpass");
+        std::fs::write(&passfile, "db1:5432:app:reader:first\n").expect("pgpass should be written");
+
+        let cfg = Config {
+            dsn: "postgresql://reader@db1:5432/app".to_string(),
+            passfile: Some(passfile.clone()),
+            ..Default::default()
+        };
+        let connect_options = cfg.connect_options().expect("options should be built");
+        let pgi = PostgresDB::lazy("test", &cfg, connect_options);
+        let password = |pgi: &PostgresDB| {
+            pgi.passwords
+                .read()
+                .expect("passwords lock")
+                .get("app")
+                .cloned()
+        };
+
+        pgi.refresh_password()
+            .expect("password should be refreshed");
+        assert_eq!(password(&pgi), Some("first".to_string()));
+
+        std::fs::write(&passfile, "db1:5432:app:reader:rotated\n")
+            .expect("pgpass should be written");
+        pgi.refresh_password()
+            .expect("password should be refreshed");
+        assert_eq!(password(&pgi), Some("rotated".to_string()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
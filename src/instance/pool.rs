@@ -0,0 +1,128 @@
+use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, Pool, Postgres};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Acquire bookkeeping of a pool, sqlx reports neither the acquires waiting for a connection
+/// nor how busy the pool was between two scrapes.
+#[derive(Debug, Default)]
+pub struct PoolUsage {
+    in_use: AtomicI64,
+    waiting: AtomicI64,
+    peak_in_use: AtomicI64,
+    peak_waiting: AtomicI64,
+}
+
+impl PoolUsage {
+    /// Returns the highest numbers of connections in use and of waiting acquires since the
+    /// previous call.
+    pub fn take_peaks(&self) -> (i64, i64) {
+        let in_use = self
+            .peak_in_use
+            .swap(self.in_use.load(Ordering::Relaxed), Ordering::Relaxed);
+        let waiting = self
+            .peak_waiting
+            .swap(self.waiting.load(Ordering::Relaxed), Ordering::Relaxed);
+        (in_use, waiting)
+    }
+
+    fn enter(current: &AtomicI64, peak: &AtomicI64) {
+        let value = current.fetch_add(1, Ordering::Relaxed) + 1;
+        peak.fetch_max(value, Ordering::Relaxed);
+    }
+}
+
+// Counts an acquire as waiting until it completes or is cancelled with the collector.
+struct Waiting<'a>(&'a PoolUsage);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A pool connection counted as in use until it is dropped.
+#[derive(Debug)]
+pub struct TrackedConnection {
+    conn: PoolConnection<Postgres>,
+    usage: Arc<PoolUsage>,
+}
+
+impl Deref for TrackedConnection {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
+
+impl DerefMut for TrackedConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.conn
+    }
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        self.usage.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A pool whose acquires are recorded in its [`PoolUsage`].
+#[derive(Debug, Clone)]
+pub struct TrackedPool {
+    pool: Pool<Postgres>,
+    usage: Arc<PoolUsage>,
+}
+
+impl TrackedPool {
+    pub fn new(pool: Pool<Postgres>, usage: Arc<PoolUsage>) -> Self {
+        Self { pool, usage }
+    }
+
+    pub fn usage(&self) -> &PoolUsage {
+        &self.usage
+    }
+
+    /// Acquires a connection, queries run on it with `&mut *conn`.
+    pub async fn acquire(&self) -> Result<TrackedConnection, sqlx::Error> {
+        PoolUsage::enter(&self.usage.waiting, &self.usage.peak_waiting);
+        let waiting = Waiting(&self.usage);
+        let conn = self.pool.acquire().await?;
+        drop(waiting);
+
+        PoolUsage::enter(&self.usage.in_use, &self.usage.peak_in_use);
+        Ok(TrackedConnection {
+            conn,
+            usage: self.usage.clone(),
+        })
+    }
+}
+
+impl Deref for TrackedPool {
+    type Target = Pool<Postgres>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peaks_reset_to_current_usage() {
+        let usage = PoolUsage::default();
+        PoolUsage::enter(&usage.in_use, &usage.peak_in_use);
+        PoolUsage::enter(&usage.in_use, &usage.peak_in_use);
+        PoolUsage::enter(&usage.waiting, &usage.peak_waiting);
+        usage.in_use.fetch_sub(1, Ordering::Relaxed);
+        usage.waiting.fetch_sub(1, Ordering::Relaxed);
+
+        assert_eq!(usage.take_peaks(), (2, 1));
+        // the connection still in use is the peak of the next scrape.
+        assert_eq!(usage.take_peaks(), (1, 0));
+    }
+}
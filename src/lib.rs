@@ -6,7 +6,11 @@ pub mod cli;
 pub mod collectors;
 pub mod config;
 pub mod instance;
+pub mod metrics;
+pub mod probe;
+pub mod reload;
 pub mod util;
+pub mod web_config;
 
 pub fn logger_init() {
     // TODO: get debug flag from config or env and set log level.
@@ -4,6 +4,10 @@ mod collectors;
 mod config;
 mod error;
 mod instance;
+mod metrics;
+mod probe;
+mod reload;
+mod web_config;
 
 use clap::Parser;
 use pg_exporter::util::version;
@@ -12,34 +16,19 @@ use std::sync::Arc;
 use std::{io, process::exit};
 
 use actix_web::{
-    App, HttpRequest, HttpResponse, HttpServer, Responder, get, http::header::ContentType, web,
+    App, HttpRequest, HttpResponse, HttpServer, Responder, get, http::header::ContentType,
+    middleware, rt, web,
 };
 
 use prometheus::Encoder;
-use prometheus::core::Collector;
 use tracing::{error, info};
 
 use crate::app::PGEApp;
 use crate::config::{ExporterConfig, Overrides};
 use crate::error::MetricsError;
+use crate::reload::Reloader;
 use pg_exporter::cli::{self, Commands};
 
-fn register_collector<C>(
-    app: &mut PGEApp,
-    dbi: Arc<instance::PostgresDB>,
-    new_fn: fn(Arc<instance::PostgresDB>) -> Option<C>,
-) -> anyhow::Result<()>
-where
-    C: collectors::PG + Collector + Clone + 'static,
-{
-    if let Some(c) = new_fn(dbi) {
-        let boxed: Box<dyn Collector> = Box::new(c.clone());
-        app.registry.register(boxed)?;
-        app.add_collector(Box::new(c));
-    }
-    Ok(())
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let args = cli::Cli::parse();
@@ -86,7 +75,7 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    ec.config.overrides(overrides);
+    ec.config.overrides(overrides.clone());
 
     info!(
         "🐘 PgExporter at http://{}{} with version {} and config({:?})",
@@ -96,7 +85,7 @@ async fn main() -> std::io::Result<()> {
         ec.config_path,
     );
 
-    match pgexporter(args.command, ec).await {
+    match pgexporter(args.command, ec, overrides).await {
         Ok(_) => return Ok(()),
         Err(e) => {
             error!("PgExporter crashed with error: {}", e);
@@ -110,7 +99,10 @@ async fn hello() -> impl Responder {
     HttpResponse::Ok().body("This is a PgExporter for Prometheus written in Rust")
 }
 
-async fn metrics(req: HttpRequest, data: web::Data<PGEApp>) -> Result<HttpResponse, MetricsError> {
+async fn metrics(
+    req: HttpRequest,
+    reloader: web::Data<Reloader>,
+) -> Result<HttpResponse, MetricsError> {
     info!(
         "processing the request from {:?}",
         req.headers()
@@ -119,24 +111,16 @@ async fn metrics(req: HttpRequest, data: web::Data<PGEApp>) -> Result<HttpRespon
             .unwrap_or("<unknown>")
     );
 
-    let tasks: Vec<_> = data
-        .collectors
-        .clone()
-        .into_iter()
-        .map(|col| {
-            actix_web::rt::spawn(async move {
-                let update_result = col.update().await;
-                match update_result {
-                    Ok(update) => update,
-                    Err(err) => error!("Problem running update collector: {err}"),
-                };
-            })
-        })
-        .collect();
+    let data = reloader.app()?;
 
-    for task in tasks {
-        task.await?;
-    }
+    let scrape_timeout = app::scrape_timeout(
+        req.headers()
+            .get("X-Prometheus-Scrape-Timeout-Seconds")
+            .and_then(|v| v.to_str().ok()),
+        data.scrape_timeout,
+    );
+
+    data.scrape(scrape_timeout).await;
 
     let process_metrics = prometheus::gather();
 
@@ -157,81 +141,129 @@ async fn metrics(req: HttpRequest, data: web::Data<PGEApp>) -> Result<HttpRespon
     Ok(resp)
 }
 
-async fn pgexporter(command: Option<Commands>, ec: ExporterConfig) -> anyhow::Result<()> {
+async fn reload(reloader: web::Data<Reloader>) -> HttpResponse {
+    match reloader.reload().await {
+        Ok(()) => HttpResponse::Ok().body("config reloaded"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Debug, serde_derive::Deserialize)]
+struct ProbeParams {
+    target: String,
+    auth_module: Option<String>,
+}
+
+async fn probe(
+    req: HttpRequest,
+    params: web::Query<ProbeParams>,
+    targets: web::Data<probe::ProbeTargets>,
+) -> Result<HttpResponse, MetricsError> {
+    info!("probing target {}", instance::redact_dsn(&params.target));
+
+    let app = match targets
+        .get(&params.target, params.auth_module.as_deref())
+        .await
+    {
+        Ok(app) => app,
+        Err(e) => {
+            error!(
+                "probe of target {} failed: {e}",
+                instance::redact_dsn(&params.target)
+            );
+            return Ok(HttpResponse::BadRequest().body(e.to_string()));
+        }
+    };
+
+    let scrape_timeout = app::scrape_timeout(
+        req.headers()
+            .get("X-Prometheus-Scrape-Timeout-Seconds")
+            .and_then(|v| v.to_str().ok()),
+        app.scrape_timeout,
+    );
+
+    app.scrape(scrape_timeout).await;
+
+    let mut buffer = Vec::new();
+    let encoder = prometheus::TextEncoder::new();
+    encoder.encode(&app.registry.gather(), &mut buffer)?;
+
+    let response = String::from_utf8(buffer).expect("Failed to convert bytes to string");
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentType::plaintext())
+        .body(response))
+}
+
+async fn pgexporter(
+    command: Option<Commands>,
+    ec: ExporterConfig,
+    overrides: Overrides,
+) -> anyhow::Result<()> {
     match command {
         None | Some(Commands::Run { .. }) => {
-            let mut app = PGEApp::new();
-
-            for (instance, config) in ec.config.instances.unwrap_or_default() {
+            let mut app = PGEApp::new()?;
+            app.scrape_timeout = ec.config.scrape_timeout();
+            app.collector_timeout = ec.config.collector_timeout();
+            app.background_collection = ec.config.background_collection.clone();
+
+            // instances which failed to initialize are retried by the next reload.
+            let mut loaded = ec.clone();
+            for (instance, config) in ec.config.instances.clone().unwrap_or_default() {
                 info!("starting connection for instance: {instance}");
 
-                let pgi = match instance::new(&instance::Config {
-                    dsn: config.dsn,
-                    exclude_db_names: config.exclude_db_names.clone(),
-                    const_labels: config.const_labels.clone(),
-                    collect_top_query: config.collect_top_query,
-                    collect_top_index: config.collect_top_index,
-                    collect_top_table: config.collect_top_table,
-                    no_track_mode: config.no_track_mode,
-                })
-                .await
+                let pgi = match instance::new(&instance, &ec.config.instance_config(&config)).await
                 {
                     Ok(p) => p,
                     Err(e) => {
                         error!("failed to initialize instance {instance}: {e}");
+                        if let Some(instances) = loaded.config.instances.as_mut() {
+                            instances.remove(&instance);
+                        }
                         continue;
                     }
                 };
 
-                let arc_pgi = Arc::new(pgi);
-
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_locks::new)?;
-                register_collector(
-                    &mut app,
-                    Arc::clone(&arc_pgi),
-                    collectors::pg_postmaster::new,
-                )?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_database::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_activity::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_bgwirter::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_wal::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_stat_io::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_archiver::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_conflict::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_indexes::new)?;
-                register_collector(
-                    &mut app,
-                    Arc::clone(&arc_pgi),
-                    collectors::pg_statements::new,
-                )?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_tables::new)?;
-                register_collector(&mut app, Arc::clone(&arc_pgi), collectors::pg_storage::new)?;
-                register_collector(
-                    &mut app,
-                    Arc::clone(&arc_pgi),
-                    collectors::pg_replication::new,
-                )?;
-                register_collector(
-                    &mut app,
-                    Arc::clone(&arc_pgi),
-                    collectors::pg_replication_slots::new,
-                )?;
-
-                app.instances.push(arc_pgi);
+                app.register_instance(Arc::new(pgi))?;
             }
 
-            HttpServer::new(move || {
+            app.start_background_collection();
+
+            let reloader = web::Data::new(Reloader::new(app, loaded, overrides)?);
+            reload_on_sighup(reloader.clone().into_inner())?;
+
+            let probe_targets = web::Data::new(probe::ProbeTargets::new(ec.config.clone())?);
+
+            let web_cfg = ec.config.web.clone().unwrap_or_default();
+            let basic_auth = web::Data::new(web_config::BasicAuth::new(
+                web_cfg.basic_auth_users.clone().unwrap_or_default(),
+            ));
+
+            let server = HttpServer::new(move || {
                 App::new()
-                    .app_data(web::Data::new(app.clone()))
+                    .wrap(middleware::from_fn(web_config::basic_auth))
+                    .app_data(basic_auth.clone())
+                    .app_data(reloader.clone())
+                    .app_data(probe_targets.clone())
                     .service(hello)
+                    .route("/probe", web::get().to(probe))
+                    .route("/-/reload", web::post().to(reload))
                     .route(
                         &ec.config.endpoint.clone().unwrap_or_default(),
                         web::get().to(metrics),
                     )
-            })
-            .bind(ec.config.listen_addr.unwrap_or_default())?
-            .run()
-            .await?
+            });
+
+            let listen_addr = ec.config.listen_addr.clone().unwrap_or_default();
+            let server = match &web_cfg.tls_server_config {
+                Some(tls) => {
+                    info!("🔒 serving HTTPS");
+                    server.bind_rustls_0_23(listen_addr, tls.server_config()?)?
+                }
+                None => server.bind(listen_addr)?,
+            };
+
+            server.run().await?
         }
 
         Some(ref _command) => {}
@@ -241,3 +273,25 @@ async fn pgexporter(command: Option<Commands>, ec: ExporterConfig) -> anyhow::Re
 
     Ok(())
 }
+
+/// Reloads the config every time the process receives SIGHUP.
+#[cfg(unix)]
+fn reload_on_sighup(reloader: Arc<Reloader>) -> anyhow::Result<()> {
+    use rt::signal::unix::{SignalKind, signal};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received");
+            // the outcome is logged and exported by the reloader.
+            let _ = reloader.reload().await;
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn reload_on_sighup(_reloader: Arc<Reloader>) -> anyhow::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use prometheus::core::Collector;
+use prometheus::{
+    Gauge, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+};
+
+use crate::instance;
+
+const EXPORTER_NAMESPACE: &str = "pg_exporter";
+
+/// Exporter-internal metrics describing how collectors behave.
+#[derive(Debug, Clone)]
+pub struct ExporterMetrics {
+    collector_duration_seconds: GaugeVec,
+    collector_last_success: IntGaugeVec,
+    scrape_errors_total: IntCounterVec,
+    collector_timeouts_total: IntCounterVec,
+    pool_connections: IntGaugeVec,
+    pool_max_connections: IntGaugeVec,
+    pool_peak_in_use_connections: IntGaugeVec,
+    pool_waiters: IntGaugeVec,
+    collector_staleness_seconds: GaugeVec,
+}
+
+/// Utilization of a connection pool, the peaks cover the time since the previous scrape.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+    pub peak_in_use: i64,
+    pub peak_waiting: i64,
+}
+
+impl ExporterMetrics {
+    pub fn new() -> anyhow::Result<Self> {
+        let collector_duration_seconds = GaugeVec::new(
+            Opts::new(
+                "collector_duration_seconds",
+                "Duration of the last update of the collector, in seconds.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "collector"],
+        )?;
+
+        let collector_last_success = IntGaugeVec::new(
+            Opts::new(
+                "collector_last_success",
+                "Whether the last update of the collector succeeded: 1 is success, 0 is failure.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "collector"],
+        )?;
+
+        let scrape_errors_total = IntCounterVec::new(
+            Opts::new(
+                "scrape_errors_total",
+                "Total number of failed collector updates.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "collector"],
+        )?;
+
+        let collector_timeouts_total = IntCounterVec::new(
+            Opts::new(
+                "collector_timeouts_total",
+                "Total number of collector updates cancelled by a timeout.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "collector"],
+        )?;
+
+        let pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "pool_connections",
+                "Number of connections of the exporter pool by state: idle or in_use.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "database", "state"],
+        )?;
+
+        let pool_max_connections = IntGaugeVec::new(
+            Opts::new(
+                "pool_max_connections",
+                "Maximum number of connections of the exporter pool.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "database"],
+        )?;
+
+        let pool_peak_in_use_connections = IntGaugeVec::new(
+            Opts::new(
+                "pool_peak_in_use_connections",
+                "Highest number of connections of the exporter pool used by collectors since the previous scrape.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "database"],
+        )?;
+
+        let pool_waiters = IntGaugeVec::new(
+            Opts::new(
+                "pool_waiters",
+                "Highest number of collector queries waiting for a free connection of the exporter pool since the previous scrape.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "database"],
+        )?;
+
+        let collector_staleness_seconds = GaugeVec::new(
+            Opts::new(
+                "collector_staleness_seconds",
+                "Time since the last successful update of the collector, in seconds.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+            &["instance", "collector"],
+        )?;
+
+        Ok(Self {
+            collector_duration_seconds,
+            collector_last_success,
+            scrape_errors_total,
+            collector_timeouts_total,
+            pool_connections,
+            pool_max_connections,
+            pool_peak_in_use_connections,
+            pool_waiters,
+            collector_staleness_seconds,
+        })
+    }
+
+    pub fn register(&self, registry: &Registry) -> anyhow::Result<()> {
+        let collectors: [Box<dyn Collector>; 9] = [
+            Box::new(self.collector_duration_seconds.clone()),
+            Box::new(self.collector_last_success.clone()),
+            Box::new(self.scrape_errors_total.clone()),
+            Box::new(self.collector_timeouts_total.clone()),
+            Box::new(self.pool_connections.clone()),
+            Box::new(self.pool_max_connections.clone()),
+            Box::new(self.pool_peak_in_use_connections.clone()),
+            Box::new(self.pool_waiters.clone()),
+            Box::new(self.collector_staleness_seconds.clone()),
+        ];
+
+        for c in collectors {
+            registry.register(c)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the outcome of a single collector update.
+    pub fn observe_update(&self, instance: &str, collector: &str, elapsed: Duration, ok: bool) {
+        let labels = [instance, collector];
+
+        self.collector_duration_seconds
+            .with_label_values(&labels)
+            .set(elapsed.as_secs_f64());
+        self.collector_last_success
+            .with_label_values(&labels)
+            .set(i64::from(ok));
+
+        // touch the counter so that it is exported with zero value before the first error.
+        let errors = self.scrape_errors_total.with_label_values(&labels);
+        if !ok {
+            errors.inc();
+        }
+    }
+
+    /// Records a collector update cancelled by a timeout.
+    pub fn observe_timeout(&self, instance: &str, collector: &str) {
+        self.collector_timeouts_total
+            .with_label_values(&[instance, collector])
+            .inc();
+    }
+
+    /// Records the age of the last successful update of a collector.
+    pub fn observe_staleness(&self, instance: &str, collector: &str, age: Duration) {
+        self.collector_staleness_seconds
+            .with_label_values(&[instance, collector])
+            .set(age.as_secs_f64());
+    }
+
+    /// Drops the series of a collector of a removed instance.
+    pub fn remove_collector(&self, instance: &str, collector: &str) {
+        let labels = [instance, collector];
+
+        // series which were never observed are missing, there is nothing to remove then.
+        let _ = self.collector_duration_seconds.remove_label_values(&labels);
+        let _ = self.collector_last_success.remove_label_values(&labels);
+        let _ = self.scrape_errors_total.remove_label_values(&labels);
+        let _ = self.collector_timeouts_total.remove_label_values(&labels);
+        let _ = self
+            .collector_staleness_seconds
+            .remove_label_values(&labels);
+    }
+
+    /// Drops the pool series, so that pools closed since the previous scrape are not reported.
+    pub fn reset_pools(&self) {
+        self.pool_connections.reset();
+        self.pool_max_connections.reset();
+        self.pool_peak_in_use_connections.reset();
+        self.pool_waiters.reset();
+    }
+
+    /// Records the utilization of a connection pool.
+    pub fn observe_pool(&self, instance: &str, database: &str, stats: &PoolStats) {
+        let size = i64::from(stats.size);
+        let idle = i64::try_from(stats.idle).unwrap_or(i64::MAX).min(size);
+        let labels = [instance, database];
+
+        self.pool_connections
+            .with_label_values(&[instance, database, "idle"])
+            .set(idle);
+        self.pool_connections
+            .with_label_values(&[instance, database, "in_use"])
+            .set(size - idle);
+        self.pool_max_connections
+            .with_label_values(&labels)
+            .set(i64::from(stats.max));
+        self.pool_peak_in_use_connections
+            .with_label_values(&labels)
+            .set(stats.peak_in_use);
+        self.pool_waiters
+            .with_label_values(&labels)
+            .set(stats.peak_waiting);
+    }
+}
+
+/// Health of a monitored instance, labelled with the instance name and its const labels.
+#[derive(Debug, Clone)]
+pub struct InstanceHealth {
+    up: IntGauge,
+    last_scrape_error: IntGauge,
+    connection_ssl: IntGaugeVec,
+}
+
+impl InstanceHealth {
+    pub fn new(instance: &str, const_labels: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut labels = const_labels.clone();
+        labels
+            .entry("instance".to_string())
+            .or_insert_with(|| instance.to_string());
+
+        let up = IntGauge::with_opts(
+            Opts::new("up", "State of PostgreSQL service: 0 is down, 1 is up.")
+                .namespace("pg")
+                .const_labels(labels.clone()),
+        )?;
+
+        let last_scrape_error = IntGauge::with_opts(
+            Opts::new(
+                "last_scrape_error",
+                "Whether the last scrape of the instance failed: 1 is error, 0 is success.",
+            )
+            .namespace(EXPORTER_NAMESPACE)
+            .const_labels(labels.clone()),
+        )?;
+
+        let connection_ssl = IntGaugeVec::new(
+            Opts::new(
+                "connection_ssl",
+                "Whether the exporter connection uses SSL: 1 is SSL, 0 is plain text.",
+            )
+            .namespace(EXPORTER_NAMESPACE)
+            .const_labels(labels),
+            &["version", "cipher"],
+        )?;
+
+        Ok(Self {
+            up,
+            last_scrape_error,
+            connection_ssl,
+        })
+    }
+
+    pub fn register(&self, registry: &Registry) -> anyhow::Result<()> {
+        registry.register(Box::new(self.up.clone()))?;
+        registry.register(Box::new(self.last_scrape_error.clone()))?;
+        registry.register(Box::new(self.connection_ssl.clone()))?;
+
+        Ok(())
+    }
+
+    pub fn unregister(&self, registry: &Registry) -> anyhow::Result<()> {
+        registry.unregister(Box::new(self.up.clone()))?;
+        registry.unregister(Box::new(self.last_scrape_error.clone()))?;
+        registry.unregister(Box::new(self.connection_ssl.clone()))?;
+
+        Ok(())
+    }
+
+    /// Records the outcome of a scrape: whether the instance answered the health check and
+    /// whether any of its collectors failed.
+    pub fn set(&self, up: bool, collector_failed: bool) {
+        self.up.set(i64::from(up));
+        self.last_scrape_error
+            .set(i64::from(!up || collector_failed));
+    }
+
+    /// Records the SSL state of the exporter connection, `None` when the instance is unreachable.
+    pub fn set_ssl(&self, state: Option<&instance::SslState>) {
+        self.connection_ssl.reset();
+        if let Some(state) = state {
+            self.connection_ssl
+                .with_label_values(&[state.version.as_str(), state.cipher.as_str()])
+                .set(i64::from(state.ssl));
+        }
+    }
+}
+
+/// Outcome of config reloads.
+#[derive(Debug, Clone)]
+pub struct ReloadMetrics {
+    last_reload_successful: IntGauge,
+    last_reload_success_timestamp_seconds: Gauge,
+    reload_failures_total: IntCounter,
+}
+
+impl ReloadMetrics {
+    pub fn new() -> anyhow::Result<Self> {
+        let last_reload_successful = IntGauge::with_opts(
+            Opts::new(
+                "config_last_reload_successful",
+                "Whether the last config reload succeeded: 1 is success, 0 is failure.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+        )?;
+
+        let last_reload_success_timestamp_seconds = Gauge::with_opts(
+            Opts::new(
+                "config_last_reload_success_timestamp_seconds",
+                "Timestamp of the last successful config reload, in seconds since the epoch.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+        )?;
+
+        let reload_failures_total = IntCounter::with_opts(
+            Opts::new(
+                "config_reload_failures_total",
+                "Total number of failed config reloads.",
+            )
+            .namespace(EXPORTER_NAMESPACE),
+        )?;
+
+        Ok(Self {
+            last_reload_successful,
+            last_reload_success_timestamp_seconds,
+            reload_failures_total,
+        })
+    }
+
+    pub fn register(&self, registry: &Registry) -> anyhow::Result<()> {
+        registry.register(Box::new(self.last_reload_successful.clone()))?;
+        registry.register(Box::new(self.last_reload_success_timestamp_seconds.clone()))?;
+        registry.register(Box::new(self.reload_failures_total.clone()))?;
+
+        Ok(())
+    }
+
+    /// Records the outcome of a reload, the initial load of the config counts as a success.
+    pub fn observe(&self, ok: bool) {
+        self.last_reload_successful.set(i64::from(ok));
+
+        if ok {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            self.last_reload_success_timestamp_seconds
+                .set(now.as_secs_f64());
+        } else {
+            self.reload_failures_total.inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_value(registry: &Registry, name: &str) -> Option<f64> {
+        let mfs = registry.gather();
+        let mf = mfs.iter().find(|mf| mf.name() == name)?;
+        let m = mf.get_metric().first()?;
+        Some(match mf.get_field_type() {
+            prometheus::proto::MetricType::COUNTER => m.get_counter().value(),
+            _ => m.get_gauge().value(),
+        })
+    }
+
+    #[test]
+    fn observe_update_records_success_and_failure() {
+        let registry = Registry::new();
+        let metrics = ExporterMetrics::new().expect("metrics should be created");
+        metrics
+            .register(&registry)
+            .expect("metrics should be registered");
+
+        metrics.observe_update("pg", "pg_locks", Duration::from_millis(250), true);
+        assert_eq!(
+            find_value(&registry, "pg_exporter_collector_duration_seconds"),
+            Some(0.25)
+        );
+        assert_eq!(
+            find_value(&registry, "pg_exporter_collector_last_success"),
+            Some(1.0)
+        );
+        assert_eq!(
+            find_value(&registry, "pg_exporter_scrape_errors_total"),
+            Some(0.0)
+        );
+
+        metrics.observe_update("pg", "pg_locks", Duration::from_millis(10), false);
+        assert_eq!(
+            find_value(&registry, "pg_exporter_collector_last_success"),
+            Some(0.0)
+        );
+        assert_eq!(
+            find_value(&registry, "pg_exporter_scrape_errors_total"),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn observe_pool_records_utilization() {
+        let registry = Registry::new();
+        let metrics = ExporterMetrics::new().expect("metrics should be created");
+        metrics
+            .register(&registry)
+            .expect("metrics should be registered");
+
+        metrics.observe_pool(
+            "pg",
+            "postgres",
+            &PoolStats {
+                size: 3,
+                idle: 1,
+                max: 10,
+                peak_in_use: 3,
+                peak_waiting: 4,
+            },
+        );
+
+        let mfs = registry.gather();
+        let connections = mfs
+            .iter()
+            .find(|mf| mf.name() == "pg_exporter_pool_connections")
+            .expect("pool connections should be reported");
+        let by_state = |state: &str| {
+            connections
+                .get_metric()
+                .iter()
+                .find(|m| m.get_label().iter().any(|l| l.value() == state))
+                .map(|m| m.get_gauge().value())
+        };
+        assert_eq!(by_state("idle"), Some(1.0));
+        assert_eq!(by_state("in_use"), Some(2.0));
+        assert_eq!(
+            find_value(&registry, "pg_exporter_pool_max_connections"),
+            Some(10.0)
+        );
+        assert_eq!(
+            find_value(&registry, "pg_exporter_pool_peak_in_use_connections"),
+            Some(3.0)
+        );
+        assert_eq!(find_value(&registry, "pg_exporter_pool_waiters"), Some(4.0));
+
+        metrics.reset_pools();
+        assert_eq!(
+            find_value(&registry, "pg_exporter_pool_max_connections"),
+            None
+        );
+    }
+
+    #[test]
+    fn instance_health_records_up_and_errors() {
+        let registry = Registry::new();
+        let health = InstanceHealth::new(
+            "pg",
+            &HashMap::from([("cluster".to_string(), "main".to_string())]),
+        )
+        .expect("health should be created");
+        health
+            .register(&registry)
+            .expect("health should be registered");
+
+        health.set(true, false);
+        assert_eq!(find_value(&registry, "pg_up"), Some(1.0));
+        assert_eq!(
+            find_value(&registry, "pg_exporter_last_scrape_error"),
+            Some(0.0)
+        );
+
+        health.set(true, true);
+        assert_eq!(
+            find_value(&registry, "pg_exporter_last_scrape_error"),
+            Some(1.0)
+        );
+
+        health.set_ssl(Some(&instance::SslState {
+            ssl: true,
+            version: "TLSv1.3".to_string(),
+            cipher: "TLS_AES_256_GCM_SHA384".to_string(),
+        }));
+        assert_eq!(
+            find_value(&registry, "pg_exporter_connection_ssl"),
+            Some(1.0)
+        );
+
+        health.set(false, false);
+        health.set_ssl(None);
+        assert_eq!(find_value(&registry, "pg_exporter_connection_ssl"), None);
+        assert_eq!(find_value(&registry, "pg_up"), Some(0.0));
+        assert_eq!(
+            find_value(&registry, "pg_exporter_last_scrape_error"),
+            Some(1.0)
+        );
+
+        let mfs = registry.gather();
+        let up = mfs
+            .iter()
+            .find(|mf| mf.name() == "pg_up")
+            .and_then(|mf| mf.get_metric().first())
+            .expect("pg_up should be reported");
+        assert!(
+            up.get_label()
+                .iter()
+                .any(|l| l.name() == "instance" && l.value() == "pg")
+        );
+        assert!(
+            up.get_label()
+                .iter()
+                .any(|l| l.name() == "cluster" && l.value() == "main")
+        );
+
+        health
+            .unregister(&registry)
+            .expect("health should be unregistered");
+        assert_eq!(find_value(&registry, "pg_up"), None);
+    }
+
+    #[test]
+    fn reload_metrics_count_failures() {
+        let registry = Registry::new();
+        let metrics = ReloadMetrics::new().expect("metrics should be created");
+        metrics
+            .register(&registry)
+            .expect("metrics should be registered");
+
+        metrics.observe(true);
+        assert_eq!(
+            find_value(&registry, "pg_exporter_config_last_reload_successful"),
+            Some(1.0)
+        );
+        assert_eq!(
+            find_value(&registry, "pg_exporter_config_reload_failures_total"),
+            Some(0.0)
+        );
+
+        metrics.observe(false);
+        assert_eq!(
+            find_value(&registry, "pg_exporter_config_last_reload_successful"),
+            Some(0.0)
+        );
+        assert_eq!(
+            find_value(&registry, "pg_exporter_config_reload_failures_total"),
+            Some(1.0)
+        );
+        assert!(
+            find_value(
+                &registry,
+                "pg_exporter_config_last_reload_success_timestamp_seconds"
+            )
+            .is_some_and(|ts| ts > 0.0)
+        );
+    }
+}
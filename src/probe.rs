@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use actix_web::rt;
+use anyhow::{anyhow, bail};
+use regex::Regex;
+use tracing::info;
+
+use crate::app::PGEApp;
+use crate::config::PGEConfig;
+use crate::instance;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ProbeKey {
+    target: String,
+    auth_module: Option<String>,
+}
+
+struct ProbeTarget {
+    app: PGEApp,
+    last_used: Instant,
+}
+
+/// Targets of the `/probe` endpoint. Connections of the most recently probed targets are kept open,
+/// the least recently used target is closed when `probe.max_targets` is exceeded.
+pub struct ProbeTargets {
+    config: PGEConfig,
+    max_targets: usize,
+    // Patterns of `probe.allowed_targets`, when unset DSN targets can't use auth modules.
+    allowed_targets: Option<Vec<Regex>>,
+    targets: Mutex<HashMap<ProbeKey, ProbeTarget>>,
+}
+
+impl ProbeTargets {
+    pub fn new(config: PGEConfig) -> anyhow::Result<Self> {
+        let probe = config.probe.clone().unwrap_or_default();
+
+        Ok(Self {
+            max_targets: probe.max_targets(),
+            allowed_targets: probe.allowed_targets()?,
+            config,
+            targets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the app collecting metrics of the target, creating it on the first probe.
+    /// The target is either the name of a configured instance or a DSN, with or without the
+    /// `postgresql://` scheme.
+    pub async fn get(&self, target: &str, auth_module: Option<&str>) -> anyhow::Result<PGEApp> {
+        let key = ProbeKey {
+            target: target.to_string(),
+            auth_module: auth_module.map(str::to_string),
+        };
+
+        if let Some(cached) = self.lock()?.get_mut(&key) {
+            cached.last_used = Instant::now();
+            return Ok(cached.app.clone());
+        }
+
+        let app = self.create(&key).await?;
+
+        let mut targets = self.lock()?;
+        // the target could be created by a concurrent probe meanwhile.
+        if let Some(cached) = targets.get_mut(&key) {
+            cached.last_used = Instant::now();
+            close(app);
+            return Ok(cached.app.clone());
+        }
+
+        if targets.len() >= self.max_targets
+            && let Some(lru) = targets
+                .iter()
+                .min_by_key(|(_, t)| t.last_used)
+                .map(|(k, _)| k.clone())
+            && let Some(evicted) = targets.remove(&lru)
+        {
+            info!(
+                "probe: close connections of target {}",
+                instance::redact_dsn(&lru.target)
+            );
+            close(evicted.app);
+        }
+
+        targets.insert(
+            key,
+            ProbeTarget {
+                app: app.clone(),
+                last_used: Instant::now(),
+            },
+        );
+
+        Ok(app)
+    }
+
+    async fn create(&self, key: &ProbeKey) -> anyhow::Result<PGEApp> {
+        // the target is used as the instance label and logged, it must not leak the password.
+        let name = instance::redact_dsn(&key.target);
+
+        let configured = self
+            .config
+            .instances
+            .as_ref()
+            .and_then(|instances| instances.get(&key.target));
+        let mut instance_cfg = match configured {
+            Some(cfg) => self.config.instance_config(cfg),
+            None => self.config.instance_config(&instance::Config {
+                dsn: target_dsn(&key.target),
+                ..Default::default()
+            }),
+        };
+
+        let mut connect_options = instance_cfg
+            .connect_options()
+            .map_err(|e| anyhow!("invalid target {name}: {e}"))?;
+
+        // auth module credentials are sent to whatever server the target points to.
+        if configured.is_none() {
+            match &self.allowed_targets {
+                Some(allowed) => {
+                    // unix socket targets are matched by the socket directory.
+                    let host = connect_options.get_socket().map_or_else(
+                        || connect_options.get_host().to_string(),
+                        |socket| socket.display().to_string(),
+                    );
+                    let address = format!("{host}:{}", connect_options.get_port());
+                    if !allowed.iter().any(|re| re.is_match(&address)) {
+                        bail!("target {name} is not allowed by probe.allowed_targets");
+                    }
+                }
+                // without an allowlist the credentials only go to configured instances.
+                None if key.auth_module.is_some() => {
+                    bail!("target {name} can use an auth module only with probe.allowed_targets")
+                }
+                None => {}
+            }
+        }
+
+        if let Some(name) = &key.auth_module {
+            let module = self
+                .config
+                .auth_modules
+                .as_ref()
+                .and_then(|modules| modules.get(name))
+                .ok_or_else(|| anyhow!("unknown auth module {name}"))?;
+            connect_options = module.apply(connect_options)?;
+
+            if module.sets_password() {
+                instance_cfg.password_file = module.password_file.clone();
+            }
+        }
+
+        info!("probe: open connections of target {name}");
+        let dbi = instance::new_with_options(&name, &instance_cfg, connect_options).await?;
+
+        let mut app = PGEApp::new()?;
+        app.scrape_timeout = self.config.scrape_timeout();
+        app.collector_timeout = self.config.collector_timeout();
+        app.register_instance(Arc::new(dbi))?;
+
+        Ok(app)
+    }
+
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<ProbeKey, ProbeTarget>>> {
+        self.targets
+            .lock()
+            .map_err(|e| anyhow!("probe targets lock poisoned: {e}"))
+    }
+}
+
+fn close(app: PGEApp) {
+    rt::spawn(async move {
+        for dbi in &app.instances {
+            dbi.close().await;
+        }
+    });
+}
+
+/// Turns `host:port/dbname` targets into a DSN, full DSNs are returned as is.
+fn target_dsn(target: &str) -> String {
+    if target.starts_with("postgres://") || target.starts_with("postgresql://") {
+        target.to_string()
+    } else {
+        format!("postgresql://{target}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthModule, ProbeConfig};
+
+    // targets on a missing unix socket fail to connect at once, the probe itself is not run.
+    fn target(dbname: &str) -> String {
+        format!("postgresql://postgres@%2Fnonexistent/{dbname}")
+    }
+
+    fn probe_targets(probe: ProbeConfig) -> ProbeTargets {
+        ProbeTargets::new(PGEConfig {
+            auth_modules: Some(HashMap::from([(
+                "monitoring".to_string(),
+                AuthModule {
+                    username: Some("monitor".to_string()),
+                    password: Some("secret".to_string()),
+                    options: Some(HashMap::from([("dbname".to_string(), "app".to_string())])),
+                    ..Default::default()
+                },
+            )])),
+            probe: Some(probe),
+            ..Default::default()
+        })
+        .expect("probe targets should be created")
+    }
+
+    fn cached(targets: &ProbeTargets) -> Vec<String> {
+        let mut cached: Vec<String> = targets
+            .lock()
+            .expect("probe targets lock")
+            .keys()
+            .map(|k| k.target.clone())
+            .collect();
+        cached.sort();
+        cached
+    }
+
+    #[actix_web::test]
+    async fn probe_target_is_cached() {
+        let targets = probe_targets(ProbeConfig::default());
+
+        let first = targets.get(&target("db1"), None).await.expect("probe");
+        let second = targets.get(&target("db1"), None).await.expect("probe");
+
+        assert!(Arc::ptr_eq(&first.instances[0], &second.instances[0]));
+        assert_eq!(cached(&targets), vec![target("db1")]);
+    }
+
+    #[actix_web::test]
+    async fn least_recently_used_target_is_evicted() {
+        let targets = probe_targets(ProbeConfig {
+            max_targets: Some(2),
+            ..Default::default()
+        });
+
+        targets.get(&target("db1"), None).await.expect("probe");
+        targets.get(&target("db2"), None).await.expect("probe");
+        // db1 is used again, so db2 is the least recently used one.
+        targets.get(&target("db1"), None).await.expect("probe");
+        targets.get(&target("db3"), None).await.expect("probe");
+
+        assert_eq!(cached(&targets), vec![target("db1"), target("db3")]);
+    }
+
+    #[actix_web::test]
+    async fn auth_module_is_applied() {
+        let targets = probe_targets(ProbeConfig {
+            allowed_targets: Some(vec!["/nonexistent:5432".to_string()]),
+            ..Default::default()
+        });
+
+        let app = targets
+            .get(&target("db1"), Some("monitoring"))
+            .await
+            .expect("probe");
+        let options = app.instances[0].db.connect_options();
+        assert_eq!(options.get_username(), "monitor");
+        assert_eq!(options.get_database(), Some("app"));
+
+        // the same target without the auth module is a separate target.
+        targets.get(&target("db1"), None).await.expect("probe");
+        assert_eq!(cached(&targets).len(), 2);
+
+        assert!(targets.get(&target("db1"), Some("unknown")).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn targets_outside_allowlist_are_rejected() {
+        let targets = probe_targets(ProbeConfig {
+            allowed_targets: Some(vec!["/nonexistent:5432".to_string()]),
+            ..Default::default()
+        });
+
+        targets
+            .get(&target("db1"), Some("monitoring"))
+            .await
+            .expect("allowed target should be probed");
+        assert!(
+            targets
+                .get("evil.example.com:5432/postgres", Some("monitoring"))
+                .await
+                .is_err()
+        );
+        assert!(
+            targets
+                .get("postgresql://db1.example.com.evil:5432/postgres", None)
+                .await
+                .is_err()
+        );
+        assert_eq!(cached(&targets), vec![target("db1")]);
+    }
+
+    #[actix_web::test]
+    async fn auth_module_needs_allowlist() {
+        let targets = probe_targets(ProbeConfig::default());
+
+        assert!(
+            targets
+                .get(
+                    "postgresql://evil.example.com:5432/postgres",
+                    Some("monitoring")
+                )
+                .await
+                .is_err()
+        );
+        assert!(
+            targets
+                .get(&target("db1"), Some("monitoring"))
+                .await
+                .is_err()
+        );
+        // without credentials of the exporter any target may still be probed.
+        targets.get(&target("db1"), None).await.expect("probe");
+        assert_eq!(cached(&targets), vec![target("db1")]);
+    }
+
+    #[test]
+    fn target_dsn_adds_scheme() {
+        assert_eq!(target_dsn("db1:5432/app"), "postgresql://db1:5432/app");
+        assert_eq!(target_dsn("postgres://db1/app"), "postgres://db1/app");
+        assert_eq!(target_dsn("postgresql://db1/app"), "postgresql://db1/app");
+    }
+}
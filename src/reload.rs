@@ -0,0 +1,318 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::{anyhow, bail};
+use tracing::{error, info, warn};
+
+use crate::app::PGEApp;
+use crate::config::{ExporterConfig, Overrides, PGEConfig};
+use crate::instance::{self, PostgresDB};
+use crate::metrics::ReloadMetrics;
+
+/// Applies changes of `pg_exporter.yml` to the running exporter. Instances which are added,
+/// removed or changed are torn down and created again, unchanged ones are kept untouched.
+/// A failed reload keeps the previous config.
+pub struct Reloader {
+    config_path: PathBuf,
+    overrides: Overrides,
+    config: Mutex<PGEConfig>,
+    app: RwLock<PGEApp>,
+    metrics: ReloadMetrics,
+    reloading: AtomicBool,
+}
+
+impl Reloader {
+    pub fn new(app: PGEApp, ec: ExporterConfig, overrides: Overrides) -> anyhow::Result<Self> {
+        let metrics = ReloadMetrics::new()?;
+        metrics.register(&app.registry)?;
+        metrics.observe(true);
+
+        Ok(Self {
+            config_path: ec.config_path,
+            overrides,
+            config: Mutex::new(ec.config),
+            app: RwLock::new(app),
+            metrics,
+            reloading: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the app with the instances of the current config.
+    pub fn app(&self) -> anyhow::Result<PGEApp> {
+        self.app
+            .read()
+            .map(|app| app.clone())
+            .map_err(|e| anyhow!("app lock poisoned: {e}"))
+    }
+
+    /// Reloads the config file and applies the changed instances.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        if self.reloading.swap(true, Ordering::SeqCst) {
+            bail!("reload already in progress");
+        }
+        let _reloading = ReloadingGuard(&self.reloading);
+
+        info!("reloading config {}", self.config_path.display());
+        let result = self.apply().await;
+
+        self.metrics.observe(result.is_ok());
+        match &result {
+            Ok(()) => info!("config reloaded"),
+            Err(e) => error!("config reload failed, the previous config is kept: {e}"),
+        }
+
+        result
+    }
+
+    async fn apply(&self) -> anyhow::Result<()> {
+        let mut ec = ExporterConfig::load(&self.config_path)?;
+        ec.config.overrides(self.overrides.clone());
+
+        // sections applied only on restart keep their running values, so that a later reload
+        // compares against what is actually in effect.
+        let (new_config, diff) = {
+            let config = self.lock_config()?;
+            if config.restart_required(&ec.config) {
+                warn!(
+                    "only instances, custom_queries, collectors and settings_info are reloaded, restart to apply other changes"
+                );
+            }
+            let new_config = config.reloaded(ec.config);
+            let diff = config.instances_diff(&new_config);
+            (new_config, diff)
+        };
+
+        if diff.is_empty() {
+            info!("instances are unchanged");
+            *self.lock_config()? = new_config;
+            return Ok(());
+        }
+
+        // instances are opened before touching the running ones, an invalid one keeps the old config.
+        let mut added = Vec::with_capacity(diff.added.len());
+        for (name, cfg) in &diff.added {
+            info!("starting connection for instance: {name}");
+            match instance::new(name, cfg).await {
+                Ok(dbi) => added.push(Arc::new(dbi)),
+                Err(e) => {
+                    close(&added).await;
+                    bail!("instance {name}: {e}");
+                }
+            }
+        }
+
+        let (result, to_close) = {
+            let mut app = self
+                .app
+                .write()
+                .map_err(|e| anyhow!("app lock poisoned: {e}"))?;
+
+            let removed: Vec<_> = diff
+                .removed
+                .iter()
+                .filter_map(|name| app.remove_instance(name))
+                .collect();
+
+            match register_all(&mut app, &added) {
+                Ok(()) => {
+                    for dbi in &added {
+                        app.start_background_collection_for(&dbi.name);
+                    }
+                    *self.lock_config()? = new_config;
+                    (Ok(()), removed)
+                }
+                Err(e) => {
+                    // bring the removed instances back, they were registered before.
+                    if let Err(e) = register_all(&mut app, &removed) {
+                        error!("unable to restore instances: {e}");
+                    }
+                    for dbi in &removed {
+                        app.start_background_collection_for(&dbi.name);
+                    }
+                    (Err(e), added)
+                }
+            }
+        };
+
+        close(&to_close).await;
+        result?;
+
+        for name in &diff.removed {
+            if !diff.added.iter().any(|(added, _)| added == name) {
+                info!("instance {name} removed");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn lock_config(&self) -> anyhow::Result<std::sync::MutexGuard<'_, PGEConfig>> {
+        self.config
+            .lock()
+            .map_err(|e| anyhow!("config lock poisoned: {e}"))
+    }
+}
+
+// Clears the in-progress flag even when the reload future is dropped.
+struct ReloadingGuard<'a>(&'a AtomicBool);
+
+impl Drop for ReloadingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+// Registers all instances or none of them.
+fn register_all(app: &mut PGEApp, instances: &[Arc<PostgresDB>]) -> anyhow::Result<()> {
+    for (i, dbi) in instances.iter().enumerate() {
+        if let Err(e) = app.register_instance(Arc::clone(dbi)) {
+            for registered in &instances[..i] {
+                app.remove_instance(&registered.name);
+            }
+            bail!("instance {}: {e}", dbi.name);
+        }
+    }
+
+    Ok(())
+}
+
+async fn close(instances: &[Arc<PostgresDB>]) {
+    for dbi in instances {
+        dbi.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // instances on a missing unix socket are created without reaching a server.
+    fn instance_yaml(name: &str, dbname: &str, label: &str) -> String {
+        format!(
+            "  {name}:\n    dsn: postgresql://postgres@%2Fnonexistent/{dbname}\n    const_labels:\n      server: {label}\n"
+        )
+    }
+
+    // pg_replication exports its placeholder row before the first update, it is left out as
+    // the tests gather metrics without updating collectors.
+    fn write_config(path: &std::path::Path, scrape_timeout: u32, instances: &[String]) {
+        let yaml = format!(
+            "scrape_timeout_seconds: {scrape_timeout}\ncollectors:\n  exclude: [pg_replication]\ninstances:\n{}",
+            instances.concat()
+        );
+        std::fs::write(path, yaml).expect("config should be written");
+    }
+
+    async fn reloader(path: &std::path::Path) -> Reloader {
+        let ec = ExporterConfig::load(path).expect("config should load");
+        let mut app = PGEApp::new().expect("app should be created");
+        for (name, cfg) in ec.config.instances.clone().unwrap_or_default() {
+            let dbi = instance::new(&name, &ec.config.instance_config(&cfg))
+                .await
+                .expect("instance should be created");
+            app.register_instance(Arc::new(dbi))
+                .expect("instance should be registered");
+        }
+
+        Reloader::new(app, ec, Overrides::default()).expect("reloader should be created")
+    }
+
+    fn instance(reloader: &Reloader, name: &str) -> Option<Arc<PostgresDB>> {
+        let app = reloader.app().expect("app");
+        app.instances.iter().find(|dbi| dbi.name == name).cloned()
+    }
+
+    fn last_reload_successful(reloader: &Reloader) -> Option<f64> {
+        let app = reloader.app().expect("app");
+        let mfs = app.registry.gather();
+        let mf = mfs
+            .iter()
+            .find(|mf| mf.name() == "pg_exporter_config_last_reload_successful")?;
+        mf.get_metric().first().map(|m| m.get_gauge().value())
+    }
+
+    #[actix_web::test]
+    async fn reload_replaces_changed_instances() {
+        let path = std::env::temp_dir().join("pge_test_reload_applied.yml");
+        write_config(
+            &path,
+            10,
+            &[
+                instance_yaml("a", "a", "alpha"),
+                instance_yaml("b", "b", "bravo"),
+            ],
+        );
+        let reloader = reloader(&path).await;
+        let old_a = instance(&reloader, "a").expect("instance a");
+        let old_b = instance(&reloader, "b").expect("instance b");
+
+        write_config(
+            &path,
+            20,
+            &[
+                instance_yaml("a", "a2", "alpha"),
+                instance_yaml("b", "b", "bravo"),
+                instance_yaml("c", "c", "charlie"),
+            ],
+        );
+        reloader.reload().await.expect("reload should succeed");
+
+        let new_a = instance(&reloader, "a").expect("instance a");
+        assert!(!Arc::ptr_eq(&old_a, &new_a));
+        assert_eq!(new_a.db.connect_options().get_database(), Some("a2"));
+        assert!(Arc::ptr_eq(
+            &old_b,
+            &instance(&reloader, "b").expect("instance b")
+        ));
+        assert!(instance(&reloader, "c").is_some());
+        assert_eq!(last_reload_successful(&reloader), Some(1.0));
+
+        // scrape_timeout_seconds needs a restart, the running value stays in the config.
+        let config = reloader.lock_config().expect("config").clone();
+        assert_eq!(config.scrape_timeout_seconds, Some(10.0));
+        assert_eq!(config.instances.map(|i| i.len()), Some(3));
+    }
+
+    #[actix_web::test]
+    async fn failed_reload_restores_instances() {
+        let path = std::env::temp_dir().join("pge_test_reload_failed.yml");
+        write_config(
+            &path,
+            10,
+            &[
+                instance_yaml("a", "a", "alpha"),
+                instance_yaml("b", "b", "bravo"),
+            ],
+        );
+        let reloader = reloader(&path).await;
+        let old_a = instance(&reloader, "a").expect("instance a");
+        let old_config = reloader.lock_config().expect("config").clone();
+
+        // c has the const labels of b, registering its metrics fails after a was replaced.
+        write_config(
+            &path,
+            10,
+            &[
+                instance_yaml("a", "a2", "alpha"),
+                instance_yaml("b", "b", "bravo"),
+                instance_yaml("c", "c", "bravo"),
+            ],
+        );
+        let err = reloader.reload().await.expect_err("reload should fail");
+        assert!(err.to_string().contains("Duplicate metrics collector"));
+
+        assert!(Arc::ptr_eq(
+            &old_a,
+            &instance(&reloader, "a").expect("instance a should be restored")
+        ));
+        assert!(instance(&reloader, "b").is_some());
+        assert!(instance(&reloader, "c").is_none());
+        assert_eq!(*reloader.lock_config().expect("config"), old_config);
+        assert_eq!(last_reload_successful(&reloader), Some(0.0));
+
+        // the restored instance exports its metrics again.
+        let app = reloader.app().expect("app");
+        assert!(app.registry.gather().iter().any(|mf| mf.name() == "pg_up"));
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use anyhow::{anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use tracing::{info, warn};
+
+// How often the certificate files are checked for changes.
+const CERT_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+// Verified for unknown users, so that a response does not reveal whether the user exists.
+const DUMMY_BCRYPT_HASH: &str = "$2b$10$ftC7OLMNDII1mT5Pso0JG.cAcibiweMmXdMu0YkEaCvJh9aqy2DxC";
+
+/// HTTP server settings in the format of the Prometheus exporter-toolkit web config file.
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct WebConfig {
+    pub tls_server_config: Option<TlsServerConfig>,
+    /// Usernames and their bcrypt-hashed passwords.
+    pub basic_auth_users: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Default, Clone, serde_derive::Deserialize, PartialEq, Eq)]
+pub struct TlsServerConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    pub client_auth_type: Option<ClientAuthType>,
+    /// CA certificates client certificates are verified with.
+    pub client_ca_file: Option<PathBuf>,
+    pub min_version: Option<TlsVersion>,
+    pub max_version: Option<TlsVersion>,
+}
+
+#[derive(Debug, Default, Clone, Copy, serde_derive::Deserialize, PartialEq, Eq)]
+pub enum ClientAuthType {
+    #[default]
+    NoClientCert,
+    VerifyClientCertIfGiven,
+    RequireAndVerifyClientCert,
+    // Accepting client certificates without verification is not supported by rustls.
+    RequestClientCert,
+    RequireAnyClientCert,
+}
+
+#[derive(Debug, Clone, Copy, serde_derive::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    TLS10,
+    TLS11,
+    TLS12,
+    TLS13,
+}
+
+impl WebConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(tls) = &self.tls_server_config {
+            tls.server_config()?;
+        }
+
+        for (user, hash) in self.basic_auth_users.iter().flatten() {
+            hash.parse::<bcrypt::HashParts>()
+                .map_err(|e| anyhow!("basic_auth_users: {user}: invalid bcrypt hash: {e}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TlsServerConfig {
+    /// Builds the rustls config. The certificate and the key are reloaded when their files change.
+    pub fn server_config(&self) -> anyhow::Result<ServerConfig> {
+        let versions = self.protocol_versions()?;
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_protocol_versions(&versions)?;
+
+        let client_auth = self.client_auth_type.unwrap_or_default();
+        let builder = match client_auth {
+            ClientAuthType::NoClientCert => builder.with_no_client_auth(),
+            ClientAuthType::VerifyClientCertIfGiven
+            | ClientAuthType::RequireAndVerifyClientCert => {
+                let ca_file = self.client_ca_file.as_ref().ok_or_else(|| {
+                    anyhow!("tls_server_config: client_ca_file is required by {client_auth:?}")
+                })?;
+
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(ca_file)
+                    .map_err(|e| anyhow!("tls_server_config: client_ca_file: {e}"))?
+                {
+                    roots.add(cert?)?;
+                }
+
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if client_auth == ClientAuthType::VerifyClientCertIfGiven {
+                    verifier.allow_unauthenticated().build()?
+                } else {
+                    verifier.build()?
+                };
+
+                builder.with_client_cert_verifier(verifier)
+            }
+            ClientAuthType::RequestClientCert | ClientAuthType::RequireAnyClientCert => {
+                bail!("tls_server_config: client_auth_type {client_auth:?} is not supported")
+            }
+        };
+
+        let resolver = CertReloader::new(self.cert_file.clone(), self.key_file.clone())?;
+        let mut config = builder.with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(config)
+    }
+
+    fn protocol_versions(&self) -> anyhow::Result<Vec<&'static rustls::SupportedProtocolVersion>> {
+        let min = self.min_version.unwrap_or(TlsVersion::TLS12);
+        let max = self.max_version.unwrap_or(TlsVersion::TLS13);
+
+        let versions: Vec<_> = [
+            (TlsVersion::TLS12, &rustls::version::TLS12),
+            (TlsVersion::TLS13, &rustls::version::TLS13),
+        ]
+        .into_iter()
+        .filter(|(v, _)| (min..=max).contains(v))
+        .map(|(_, version)| version)
+        .collect();
+
+        if versions.is_empty() {
+            bail!("tls_server_config: no supported TLS version between {min:?} and {max:?}");
+        }
+
+        Ok(versions)
+    }
+}
+
+#[derive(Debug)]
+struct LoadedCert {
+    key: Arc<CertifiedKey>,
+    modified: (SystemTime, SystemTime),
+    checked: Instant,
+}
+
+/// Serves the certificate from `cert_file` and `key_file`, re-reading them once they change.
+/// A broken certificate update is logged and the previous certificate stays in use.
+#[derive(Debug)]
+struct CertReloader {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    loaded: RwLock<LoadedCert>,
+}
+
+impl CertReloader {
+    fn new(cert_file: PathBuf, key_file: PathBuf) -> anyhow::Result<Self> {
+        let modified = modified(&cert_file, &key_file)?;
+        let key = load_certified_key(&cert_file, &key_file)?;
+
+        Ok(Self {
+            cert_file,
+            key_file,
+            loaded: RwLock::new(LoadedCert {
+                key,
+                modified,
+                checked: Instant::now(),
+            }),
+        })
+    }
+
+    fn reload_if_changed(&self) {
+        let Ok(mut loaded) = self.loaded.write() else {
+            return;
+        };
+        if loaded.checked.elapsed() < CERT_RELOAD_INTERVAL {
+            return;
+        }
+        loaded.checked = Instant::now();
+
+        let modified = match modified(&self.cert_file, &self.key_file) {
+            Ok(m) => m,
+            Err(e) => {
+                warn!("tls: can't check certificate files: {e}");
+                return;
+            }
+        };
+        if modified == loaded.modified {
+            return;
+        }
+        // a broken update is reported once, the files are read again on their next change.
+        loaded.modified = modified;
+
+        match load_certified_key(&self.cert_file, &self.key_file) {
+            Ok(key) => {
+                info!("tls: certificate {} reloaded", self.cert_file.display());
+                loaded.key = key;
+            }
+            Err(e) => warn!("tls: can't reload certificate, keep the previous one: {e}"),
+        }
+    }
+}
+
+impl ResolvesServerCert for CertReloader {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let stale = self
+            .loaded
+            .read()
+            .map(|l| l.checked.elapsed() >= CERT_RELOAD_INTERVAL)
+            .unwrap_or(false);
+        if stale {
+            self.reload_if_changed();
+        }
+
+        self.loaded.read().ok().map(|l| Arc::clone(&l.key))
+    }
+}
+
+fn modified(cert_file: &PathBuf, key_file: &PathBuf) -> anyhow::Result<(SystemTime, SystemTime)> {
+    Ok((
+        fs::metadata(cert_file)?.modified()?,
+        fs::metadata(key_file)?.modified()?,
+    ))
+}
+
+fn load_certified_key(
+    cert_file: &PathBuf,
+    key_file: &PathBuf,
+) -> anyhow::Result<Arc<CertifiedKey>> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("tls_server_config: cert_file: {e}"))?;
+    if certs.is_empty() {
+        bail!("tls_server_config: cert_file: no certificates found");
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| anyhow!("tls_server_config: key_file: {e}"))?;
+    let key = ring::sign::any_supported_type(&key)?;
+
+    Ok(Arc::new(CertifiedKey::new(certs, key)))
+}
+
+/// Users allowed to access the HTTP endpoints. Everybody is allowed when no users are configured.
+#[derive(Debug, Default, Clone)]
+pub struct BasicAuth {
+    users: HashMap<String, String>,
+}
+
+impl BasicAuth {
+    pub fn new(users: HashMap<String, String>) -> Self {
+        Self { users }
+    }
+
+    /// Checks the value of the `Authorization` header.
+    pub fn verify(&self, authorization: Option<&str>) -> bool {
+        let Some((user, password)) = authorization.and_then(parse_basic_auth) else {
+            return false;
+        };
+
+        match self.users.get(&user) {
+            Some(hash) => bcrypt::verify(&password, hash).unwrap_or(false),
+            None => {
+                let _ = bcrypt::verify(&password, DUMMY_BCRYPT_HASH);
+                false
+            }
+        }
+    }
+}
+
+fn parse_basic_auth(value: &str) -> Option<(String, String)> {
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Middleware rejecting requests without valid basic-auth credentials.
+pub async fn basic_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let auth = req.app_data::<web::Data<BasicAuth>>().cloned();
+
+    if let Some(auth) = auth
+        && !auth.users.is_empty()
+    {
+        let authorization = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        // bcrypt is slow on purpose, keep it off the worker thread.
+        let allowed = web::block(move || auth.verify(authorization.as_deref())).await?;
+        if !allowed {
+            let resp = HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Basic"))
+                .finish();
+            return Ok(req.into_response(resp).map_into_right_body());
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // bcrypt hash of "secret".
+    const SECRET_HASH: &str = "$2b$04$XcxlJfnMedJ4tzlIyKdGuu8Mgh3FnO8AH.CEo2LLn/gE6/6aJVp3q";
+
+    #[test]
+    fn web_config_in_exporter_toolkit_format() {
+        let yaml = r#"
+tls_server_config:
+  cert_file: server.crt
+  key_file: server.key
+  client_auth_type: RequireAndVerifyClientCert
+  client_ca_file: ca.crt
+  min_version: TLS13
+basic_auth_users:
+  prometheus: $2b$04$XcxlJfnMedJ4tzlIyKdGuu8Mgh3FnO8AH.CEo2LLn/gE6/6aJVp3q
+"#;
+        let cfg: WebConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(yaml, ::config::FileFormat::Yaml))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .expect("web config should parse");
+
+        let tls = cfg.tls_server_config.expect("tls should be present");
+        assert_eq!(tls.cert_file, PathBuf::from("server.crt"));
+        assert_eq!(
+            tls.client_auth_type,
+            Some(ClientAuthType::RequireAndVerifyClientCert)
+        );
+        assert_eq!(tls.min_version, Some(TlsVersion::TLS13));
+        assert!(
+            cfg.basic_auth_users
+                .is_some_and(|u| u.contains_key("prometheus"))
+        );
+    }
+
+    #[test]
+    fn basic_auth_verifies_credentials() {
+        let auth = BasicAuth::new(HashMap::from([(
+            "prometheus".to_string(),
+            SECRET_HASH.to_string(),
+        )]));
+
+        let header = |user: &str, password: &str| {
+            format!("Basic {}", STANDARD.encode(format!("{user}:{password}")))
+        };
+
+        assert!(auth.verify(Some(&header("prometheus", "secret"))));
+        assert!(!auth.verify(Some(&header("prometheus", "wrong"))));
+        assert!(!auth.verify(Some(&header("nobody", "secret"))));
+        assert!(!auth.verify(Some("Bearer token")));
+        assert!(!auth.verify(None));
+    }
+
+    #[test]
+    fn invalid_bcrypt_hash_fails_validation() {
+        let cfg = WebConfig {
+            basic_auth_users: Some(HashMap::from([(
+                "prometheus".to_string(),
+                "secret".to_string(),
+            )])),
+            ..Default::default()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn tls_versions_range() {
+        let tls = TlsServerConfig {
+            min_version: Some(TlsVersion::TLS13),
+            ..Default::default()
+        };
+        assert_eq!(
+            tls.protocol_versions().expect("TLS13 should be supported"),
+            vec![&rustls::version::TLS13]
+        );
+
+        let tls = TlsServerConfig {
+            max_version: Some(TlsVersion::TLS11),
+            ..Default::default()
+        };
+        assert!(tls.protocol_versions().is_err());
+    }
+}